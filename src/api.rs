@@ -1,6 +1,13 @@
-use std::{fs, path::Path};
+use std::{
+   collections::HashSet,
+   fs,
+   path::Path,
+   thread,
+   time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, FixedOffset};
 use reqwest::{
    blocking::{
       multipart::{self, Part},
@@ -11,10 +18,17 @@ use reqwest::{
 };
 use serde_json::Value;
 
-use crate::structures::{Config, Image, MessageLevel, MessageOutput, StatusVisibility};
+use crate::structures::{Config, Focus, Image, MessageLevel, MessageOutput, StatusVisibility};
 
 static GITHUB_LINK: &str = "https://github.com/Rengyr/Vulpes-Porto";
 
+/// Maximal time to wait for the server to process uploaded media
+const MEDIA_PROCESSING_TIMEOUT: Duration = Duration::from_secs(120);
+/// Time between checks of the state of uploaded media
+const MEDIA_PROCESSING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Maximal number of scheduled statuses the server returns in one page
+const SCHEDULED_STATUSES_PAGE_LIMIT: usize = 40;
+
 /// Function to get a client with the correct headers
 /// * `token` - Optional token to be used for authorization
 pub fn get_client(token: Option<&str>) -> Result<Client, Error> {
@@ -88,6 +102,9 @@ pub fn get_image_sources(sources_json_file_path: &str) -> Result<(String, Vec<Im
       Ok(images) => images,
       Err(err) => return Err(anyhow!(err).context("Unable to parse text as images json")),
    };
+   for image in &images {
+      image.validate().map_err(|err| anyhow!(err).context("Invalid image in images json"))?;
+   }
 
    Ok((images_json, images))
 }
@@ -96,12 +113,16 @@ pub fn get_image_sources(sources_json_file_path: &str) -> Result<(String, Vec<Im
 /// * `client` - Client to make requests
 /// * `app_config` - Application configuration
 /// * `image_bytes` - Bytes of the image
-/// * `image` - Image structure
+/// * `location` - Location of the image
+/// * `alt` - Optional alt text of the image
+/// * `focus` - Optional focal point of the image
 pub fn upload_image_to_media_api(
    client: &Client,
    app_config: &Config,
    image_bytes: Vec<u8>,
-   image: &Image,
+   location: &str,
+   alt: Option<&String>,
+   focus: Option<&Focus>,
 ) -> Result<String, ()> {
    let part = Part::bytes(image_bytes).file_name("image");
 
@@ -110,8 +131,12 @@ pub fn upload_image_to_media_api(
       // Image
       .part("file", part);
 
-   if let Some(alt) = image.alt.clone() {
-      media_request = media_request.text("description", alt);
+   if let Some(alt) = alt {
+      media_request = media_request.text("description", alt.to_owned());
+   }
+
+   if let Some(focus) = focus {
+      media_request = media_request.text("focus", focus.to_string());
    }
 
    let response = client.post(app_config.server.to_owned() + "/api/v2/media").multipart(media_request).send();
@@ -120,7 +145,7 @@ pub fn upload_image_to_media_api(
       Ok(response) => response,
       Err(e) => {
          app_config.output_message(
-            &format!("Unable to post image to /api/v2/media for image {}.\nError: {:#}", image.location, e),
+            &format!("Unable to post image to /api/v2/media for image {}.\nError: {:#}", location, e),
             MessageLevel::Error,
             MessageOutput::Stderr,
          );
@@ -128,9 +153,10 @@ pub fn upload_image_to_media_api(
       }
    };
 
+   let status = response.status();
    if !response.status().is_success() {
       app_config.output_message(
-         &format!("Wrong status from media api: {} for image {}", response.status(), image.location),
+         &format!("Wrong status from media api: {} for image {}", response.status(), location),
          MessageLevel::Error,
          MessageOutput::Stderr,
       );
@@ -142,7 +168,7 @@ pub fn upload_image_to_media_api(
       Ok(media_json) => media_json,
       Err(e) => {
          app_config.output_message(
-            &format!("Unable to parse media json for image {}.\nError: {:#}", image.location, e),
+            &format!("Unable to parse media json for image {}.\nError: {:#}", location, e),
             MessageLevel::Error,
             MessageOutput::Stderr,
          );
@@ -150,36 +176,109 @@ pub fn upload_image_to_media_api(
       }
    };
 
-   match media_json["id"].as_str() {
-      Some(media_id) => Ok(media_id.to_string()),
+   let media_id = match media_json["id"].as_str() {
+      Some(media_id) => media_id.to_string(),
       None => {
          app_config.output_message(
-            &format!("Unable to get media id: {:?} for image {}", media_json, image.location),
+            &format!("Unable to get media id: {:?} for image {}", media_json, location),
             MessageLevel::Error,
             MessageOutput::Stderr,
          );
-         Err(())
+         return Err(());
       }
+   };
+
+   //Media is still being processed by the server and can't be attached to status yet
+   if status == StatusCode::ACCEPTED {
+      wait_for_media_processing(client, app_config, &media_id, location)?;
    }
+
+   Ok(media_id)
 }
 
-/// Function to make post with image on fedi
+/// Function to wait till server finishes processing of uploaded media
 /// * `client` - Client to make requests
 /// * `app_config` - Application configuration
 /// * `media_id` - Media id of uploaded image from media api
+/// * `location` - Location of the image
+fn wait_for_media_processing(client: &Client, app_config: &Config, media_id: &str, location: &str) -> Result<(), ()> {
+   let deadline = Instant::now() + MEDIA_PROCESSING_TIMEOUT;
+   loop {
+      thread::sleep(MEDIA_PROCESSING_POLL_INTERVAL);
+
+      let response = client.get(format!("{}/api/v1/media/{}", app_config.server, media_id)).send();
+      match response {
+         // Media was processed
+         Ok(response) if response.status() == StatusCode::OK => return Ok(()),
+         // Media is still being processed
+         Ok(response) if response.status() == StatusCode::PARTIAL_CONTENT => {}
+         Ok(response) => {
+            app_config.output_message(
+               &format!(
+                  "Server failed to process media {} for image {}, status: {}, response: {}",
+                  media_id,
+                  location,
+                  response.status(),
+                  response.text().unwrap_or_default()
+               ),
+               MessageLevel::Error,
+               MessageOutput::Stderr,
+            );
+            return Err(());
+         }
+         Err(e) => {
+            app_config.output_message(
+               &format!("Unable to get state of media {} for image {}.\nError: {:#}", media_id, location, e),
+               MessageLevel::Warning,
+               MessageOutput::Stderr,
+            );
+         }
+      }
+
+      if Instant::now() >= deadline {
+         app_config.output_message(
+            &format!(
+               "Media {} for image {} was not processed by server within {} seconds",
+               media_id,
+               location,
+               MEDIA_PROCESSING_TIMEOUT.as_secs()
+            ),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         return Err(());
+      }
+   }
+}
+
+/// Function to make post with image on fedi
+/// * `client` - Client to make requests
+/// * `app_config` - Application configuration
+/// * `media_ids` - Media ids of uploaded images from media api
 /// * `image` - Image structure
 /// * `visibility` - Visibility of the post
+/// * `in_reply_to_id` - Optional id of status to reply to
+/// * `idempotency_key` - Key for the server to recognize repeated request for the same status
+/// * `scheduled_at` - Optional time to publish the status at, status is scheduled on the server instead of posted
+///
+/// Returns id of the created status or of the scheduled status
+#[allow(clippy::too_many_arguments)]
 pub fn create_new_status_with_image(
    client: &Client,
    app_config: &Config,
-   media_id: String,
+   media_ids: Vec<String>,
    image: &Image,
    visibility: StatusVisibility,
-) -> Result<(), ()> {
+   in_reply_to_id: Option<&str>,
+   idempotency_key: &str,
+   scheduled_at: Option<DateTime<FixedOffset>>,
+) -> Result<String, ()> {
    //Construct request to post new post to mastodon with the image
-   let mut status_request = multipart::Form::new()
-      // Image id
-      .text("media_ids[]", media_id);
+   let mut status_request = multipart::Form::new();
+   // Image ids
+   for media_id in media_ids {
+      status_request = status_request.text("media_ids[]", media_id);
+   }
 
    if visibility != StatusVisibility::Default {
       status_request = status_request.text("visibility", visibility.to_string());
@@ -188,8 +287,8 @@ pub fn create_new_status_with_image(
    //Get the message on the image or default ""
    let mut message = image.msg.clone().unwrap_or_default();
 
-   //If tags are specified then add tags after new line if message is not empty
-   if !app_config.tags.is_empty() {
+   //If tags are specified then add tags after new line if message is not empty, replies in series are without tags
+   if !app_config.tags.is_empty() && in_reply_to_id.is_none() {
       if !message.is_empty() {
          message += "\n";
       }
@@ -206,7 +305,24 @@ pub fn create_new_status_with_image(
       status_request = status_request.text("spoiler_text", content_warning.to_owned());
    }
 
-   let response = client.post(app_config.server.to_owned() + "/api/v1/statuses").multipart(status_request).send();
+   //Add language of the image or default language
+   if let Some(language) = image.language.as_ref().or(app_config.language.as_ref()) {
+      status_request = status_request.text("language", language.to_owned());
+   }
+
+   if let Some(in_reply_to_id) = in_reply_to_id {
+      status_request = status_request.text("in_reply_to_id", in_reply_to_id.to_owned());
+   }
+
+   if let Some(scheduled_at) = scheduled_at {
+      status_request = status_request.text("scheduled_at", scheduled_at.to_rfc3339());
+   }
+
+   let response = client
+      .post(app_config.server.to_owned() + "/api/v1/statuses")
+      .header("Idempotency-Key", idempotency_key)
+      .multipart(status_request)
+      .send();
 
    let response = match response {
       Ok(response) => response,
@@ -229,7 +345,94 @@ pub fn create_new_status_with_image(
       return Err(());
    }
 
-   Ok(())
+   let status_json: Value = match serde_json::from_str(&response.text().unwrap_or_default()) {
+      Ok(status_json) => status_json,
+      Err(e) => {
+         app_config.output_message(
+            &format!("Unable to parse status json for image {}.\nError: {:#}", image.location, e),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         return Err(());
+      }
+   };
+
+   match status_json["id"].as_str() {
+      Some(status_id) => Ok(status_id.to_string()),
+      None => {
+         app_config.output_message(
+            &format!("Unable to get status id: {:?} for image {}", status_json, image.location),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         Err(())
+      }
+   }
+}
+
+/// Function to get ids of all statuses scheduled on the server
+/// * `client` - Client to make requests
+/// * `app_config` - Application configuration
+pub fn get_scheduled_status_ids(client: &Client, app_config: &Config) -> Result<HashSet<String>, ()> {
+   let mut ids = HashSet::new();
+   let mut max_id: Option<String> = None;
+   loop {
+      //Statuses are returned from the newest in pages
+      let mut url = format!("{}/api/v1/scheduled_statuses?limit={}", app_config.server, SCHEDULED_STATUSES_PAGE_LIMIT);
+      if let Some(max_id) = &max_id {
+         url += &format!("&max_id={}", max_id);
+      }
+
+      let response = match client.get(url).send() {
+         Ok(response) => response,
+         Err(e) => {
+            app_config.output_message(
+               &format!("Unable to get scheduled statuses from /api/v1/scheduled_statuses.\nError: {:#}", e),
+               MessageLevel::Error,
+               MessageOutput::Stderr,
+            );
+            return Err(());
+         }
+      };
+
+      if !response.status().is_success() {
+         app_config.output_message(
+            &format!("Wrong status from scheduled statuses api: {}", response.status()),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         return Err(());
+      }
+
+      let statuses_json: Value = match serde_json::from_str(&response.text().unwrap_or_default()) {
+         Ok(statuses_json) => statuses_json,
+         Err(e) => {
+            app_config.output_message(
+               &format!("Unable to parse scheduled statuses json.\nError: {:#}", e),
+               MessageLevel::Error,
+               MessageOutput::Stderr,
+            );
+            return Err(());
+         }
+      };
+
+      let Some(statuses) = statuses_json.as_array() else {
+         app_config.output_message(
+            &format!("Unable to get list of scheduled statuses: {:?}", statuses_json),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         return Err(());
+      };
+
+      let page_ids: Vec<String> = statuses.iter().filter_map(|status| status["id"].as_str().map(str::to_string)).collect();
+      let last_page = page_ids.len() < SCHEDULED_STATUSES_PAGE_LIMIT;
+      max_id = page_ids.last().cloned();
+      ids.extend(page_ids);
+      if last_page {
+         return Ok(ids);
+      }
+   }
 }
 
 /// Function to check connection to the server
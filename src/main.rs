@@ -2,27 +2,45 @@
 extern crate version;
 
 mod api;
+mod schedule;
 mod structures;
 
-use api::{create_new_status_with_image, get_client, get_image_sources, upload_image_to_media_api};
-use clap::{arg, command, CommandFactory, Parser};
-use structures::{save_images_ids, Config, GetImageErrorLevel, Image, ImageDB, MessageLevel, MessageOutput, StatusVisibility};
+use api::{create_new_status_with_image, get_client, get_image_sources, get_scheduled_status_ids, upload_image_to_media_api};
+use clap::{CommandFactory, Parser};
+use schedule::{check_jitter, get_next_post, plan_next_post};
+use structures::{
+   save_images_ids, Config, FailedImage, GetImageErrorLevel, IdempotencyKey, Image, ImageDB, ImageDate, MessageLevel,
+   MessageOutput, PostErrorLevel, RetryState, RngState, ScheduledPost, ScheduledStatus, Selection, SeriesProgress,
+   StatusVisibility,
+};
 
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate};
 use core::time;
-use rand::Rng;
+use rand::{
+   distributions::{Distribution, WeightedIndex},
+   rngs::StdRng,
+   Rng, SeedableRng,
+};
+use reqwest::blocking::Client;
+#[cfg(unix)]
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::{
    collections::{HashMap, HashSet},
    fs::File,
    io::{BufReader, Read},
    path::Path,
    process::exit,
-   sync::{atomic::AtomicBool, Arc},
    thread,
    time::Instant,
 };
 
+/// Longest sleep of the main loop, the monotonic clock doesn't advance while the system is suspended so the time is checked often
+const MAX_SLEEP_SECONDS: u64 = 60;
+
+/// Server requires scheduled statuses to be at least 5 minutes ahead, margin covers upload of images
+const MIN_SCHEDULE_AHEAD_MINUTES: i64 = 10;
+
 /// From link to json load new image and parse the results to ImageDB structure. Returns Hashmap with images with keys of md5 hashes or returns Error.
 /// * `app_config` - Configuration of the bot
 /// * `images_db` - Structure holding used and unused images
@@ -37,12 +55,15 @@ fn load_image_paths(
    report_duplicate_source_image_locations(app_config, &images_json, &parsed_images);
 
    //Calculate md5 hashes as keys for images
-   let images: HashMap<String, Image> = parsed_images.into_iter().map(|image| (image.get_hash(), image)).collect();
+   let images: HashMap<String, Image> =
+      parsed_images.into_iter().enumerate().map(|(position, image)| (image.get_hash(), Image { position, ..image })).collect();
 
-   //Add new images to unused list
+   //Add new images to unused list in order of image json so seeded picks don't depend on order of hashmap
    let mut new = 0;
-   for hash in images.keys() {
-      if !images_db.contains(hash) {
+   let mut ordered: Vec<(&String, &Image)> = images.iter().collect();
+   ordered.sort_by_key(|(_, image)| image.position);
+   for (hash, image) in ordered {
+      if image.post_on.is_none() && !images_db.contains(hash) {
          images_db.unused.push(hash.to_owned());
          new += 1;
       }
@@ -51,6 +72,17 @@ fn load_image_paths(
       app_config.output_message(&format!("Added {} new images", new), MessageLevel::Notice, MessageOutput::Stdout);
    }
 
+   //Images outside of their validity window stay in the lists but are not picked
+   let today = app_config.now().date_naive();
+   let unavailable = images.values().filter(|image| !image.is_available(today)).count();
+   if unavailable > 0 {
+      app_config.output_message(
+         &format!("{} images are outside of their validity window today", unavailable),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+   }
+
    //Remove images that were removed from json from the unused list
    let total = images_db.unused.len() + images_db.used.len();
    images_db.unused.retain(|hash| images.contains_key(hash));
@@ -75,12 +107,44 @@ fn load_image_paths(
          MessageOutput::Stdout,
       );
    }
+   images_db.last_posted.retain(|hash, _| images.contains_key(hash));
+
+   //Images pinned to a date are posted only on that date, so they are not in the lists of images to pick from
+   let pinned = |hash: &String| images.get(hash).is_some_and(|image| image.post_on.is_some());
+   images_db.unused.retain(|hash| !pinned(hash));
+   images_db.used.retain(|hash| !pinned(hash));
+   images_db.random_deck.retain(|hash| !pinned(hash));
+
+   //Remove images that were removed from json from the queue
+   let missing_queued: Vec<String> = images_db.queue.iter().filter(|hash| !images.contains_key(*hash)).cloned().collect();
+   for hash in &missing_queued {
+      images_db.dequeue(hash);
+   }
+   let removed_q = missing_queued.len();
+   if removed_q > 0 {
+      app_config.output_message(
+         &format!("Removed from queue {} images not found in json", removed_q),
+         MessageLevel::Notice,
+         MessageOutput::Stdout,
+      );
+   }
+
+   //Report collisions at start and then only when they change
+   let collisions = get_post_on_collisions(&images);
+   if images_old.is_none_or(|images_old| get_post_on_collisions(images_old) != collisions) {
+      for collision in collisions {
+         app_config.output_message(&collision, MessageLevel::Warning, MessageOutput::Stdout);
+      }
+   }
 
    //Check if alt text or text of images changed and write notice to stdout
    if let Some(images_old) = images_old {
       let mut message_changed = 0;
       let mut alt_changed = 0;
       let mut content_warning_changed = 0;
+      let mut weight_changed = 0;
+      let mut category_changed = 0;
+      let mut album_changed = 0;
       for image in &images {
          if let Some(image_old) = images_old.get(image.0) {
             if image_old.msg != image.1.msg {
@@ -92,6 +156,15 @@ fn load_image_paths(
             if image_old.content_warning != image.1.content_warning {
                content_warning_changed += 1;
             }
+            if image_old.weight != image.1.weight {
+               weight_changed += 1;
+            }
+            if image_old.category != image.1.category {
+               category_changed += 1;
+            }
+            if image_old.album != image.1.album {
+               album_changed += 1;
+            }
          }
       }
 
@@ -116,11 +189,80 @@ fn load_image_paths(
             MessageOutput::Stdout,
          );
       }
+      if weight_changed > 0 {
+         app_config.output_message(
+            &format!("Weight changed for {} images", weight_changed),
+            MessageLevel::Notice,
+            MessageOutput::Stdout,
+         );
+      }
+      if category_changed > 0 {
+         app_config.output_message(
+            &format!("Category changed for {} images", category_changed),
+            MessageLevel::Notice,
+            MessageOutput::Stdout,
+         );
+      }
+      if album_changed > 0 {
+         app_config.output_message(
+            &format!("Album changed for {} images", album_changed),
+            MessageLevel::Notice,
+            MessageOutput::Stdout,
+         );
+      }
    }
 
    Ok(images)
 }
 
+/// Get warnings about images pinned to be posted on the same day
+/// * `images` - Hashmap with images
+fn get_post_on_collisions(images: &HashMap<String, Image>) -> Vec<String> {
+   let mut collisions = Vec::new();
+   let mut pinned: Vec<(&Image, &ImageDate)> =
+      images.values().filter_map(|image| image.post_on.as_ref().map(|post_on| (image, post_on))).collect();
+   pinned.sort_by_key(|(image, _)| image.position);
+
+   for (index, (image, post_on)) in pinned.iter().enumerate() {
+      for (other_image, other_post_on) in pinned.iter().skip(index + 1) {
+         //Images of the same series are posted together
+         if image.series.is_some() && image.series == other_image.series {
+            continue;
+         }
+         if post_on.collides_with(other_post_on) {
+            collisions.push(format!(
+               "Images {} (post_on {}) and {} (post_on {}) are pinned to the same day, the one later in image json will be posted at the next post time",
+               image.location, post_on, other_image.location, other_post_on
+            ));
+         }
+      }
+   }
+   collisions
+}
+
+/// Get the image pinned to the nearest date and the date, images already posted today are posted again on their next date
+/// * `images` - Hashmap with images
+/// * `images_db` - Database of images
+/// * `date` - Date to search from
+fn get_next_pinned_image<'a>(
+   images: &'a HashMap<String, Image>,
+   images_db: &ImageDB,
+   date: NaiveDate,
+) -> Option<(&'a Image, NaiveDate)> {
+   images
+      .values()
+      .filter_map(|image| {
+         let post_on = image.post_on.as_ref()?;
+         let posted_today = images_db.last_posted.get(&image.get_hash()).is_some_and(|posted| posted.date_naive() == date);
+         let next_date = match post_on.next_on_or_after(date) {
+            Some(next_date) if next_date == date && posted_today => post_on.next_on_or_after(date.succ_opt()?),
+            next_date => next_date,
+         };
+         next_date.map(|next_date| (image, next_date))
+      })
+      .min_by_key(|(image, next_date)| (*next_date, image.position))
+}
+
 /// Reports images in sources file with same location
 /// * `app_config` - Configuration of the bot
 /// * `images_json` - JSON string with images for reporting actual line in file
@@ -128,7 +270,7 @@ fn load_image_paths(
 fn report_duplicate_source_image_locations(app_config: &Config, images_json: &str, parsed_images: &[Image]) {
    //Calculate md5 hashes as keys for duplicity check
    let images_hashes: Vec<(String, String)> =
-      parsed_images.iter().map(|image| (image.get_hash(), image.location.clone())).collect();
+      parsed_images.iter().map(|image| (image.get_hash(), image.location.first().clone())).collect();
 
    // Keep list of reported duplicates to avoid duplicate warnings
    let mut reported_duplicates = HashSet::new();
@@ -170,62 +312,6 @@ fn report_duplicate_source_image_locations(app_config: &Config, images_json: &st
    }
 }
 
-/// Return next closest time that is in the future given times in config or current time + 1 day if no times are configured.
-///
-/// **Times in config has to be sorted**
-#[allow(deprecated)]
-fn get_next_post_time<Tz: TimeZone>(date_time: DateTime<Tz>, config: &Config) -> DateTime<Tz> {
-   if config.times.is_empty() {
-      return date_time + chrono::Duration::days(1);
-   }
-
-   let mut current_date = date_time.date();
-   let date_time_now: DateTime<Tz> = Utc::now().with_timezone(&date_time.timezone());
-
-   //Loop until time is found
-   loop {
-      //Try all times in the config
-      for (hours, minutes) in config.times.iter() {
-         let post_date_time = match current_date.and_hms_opt(hours.to_owned() as u32, minutes.to_owned() as u32, 0) {
-            Some(new_date_time) => new_date_time,
-            None => {
-               if *hours <= 23 && *minutes <= 59 {
-                  // Check if we need to show the information about skipped time or if it's no longer relevant
-                  let Some(time) = NaiveTime::from_hms_opt(*hours as u32, *minutes as u32, 0) else {
-                     // Could be panic here, but no reason to crash service just when trying to see if to print message (and this shouldn't happen anyway)
-                     config.output_message(
-                        &format!("Can't make time {}:{} while checking Daylight Saving Time", hours, minutes),
-                        MessageLevel::Warning,
-                        MessageOutput::Stdout,
-                     );
-                     continue;
-                  };
-                  if current_date > date_time.date() || (current_date == date_time.date() && time > date_time.time()) {
-                     config.output_message(
-                        &format!("Skipped time {}:{} because it doesn't exist due to Daylight Saving Time", hours, minutes),
-                        MessageLevel::Info,
-                        MessageOutput::Stdout,
-                     );
-                  }
-                  continue; //Hours and minutes are correct, but probably daylight saving time make the specific time not exist
-               }
-               config.panic_message(
-                  &format!("Invalid hours or minutes in the configuration: hours: {}, minutes: {}", hours, minutes),
-                  MessageLevel::Critical,
-               );
-            }
-         };
-
-         if date_time_now < post_date_time {
-            return post_date_time;
-         }
-      }
-
-      //Add one day if no time in config is in the future for current day
-      current_date += chrono::Duration::days(1);
-   }
-}
-
 /// Get image data from local or remote based on the image path
 /// * `local_path` - Path to root folder
 /// * `image_path` - Path to the image from root folder
@@ -338,38 +424,24 @@ fn get_image_data_remote(remote_image_path: &str) -> Result<Vec<u8>, GetImageErr
 /// * `app_config` - Configuration of the bot
 /// * `images` - Hashmap with images
 /// * `internal_db` - Database of images
-fn post_image<'a>(app_config: &Config, images: &'a HashMap<String, Image>, internal_db: &mut ImageDB) -> Result<&'a Image, ()> {
-   let image = get_image_to_post(app_config, images, internal_db)?;
+/// * `slot` - Post time the image is posted for
+/// * `scheduled_at` - Optional time to schedule the post on the server for instead of posting it now
+///
+/// Images of a series are posted as reply chain, the series continues from the failed image on the next attempt.
+/// Scheduled statuses can't reply to each other, so images of a scheduled series are separate posts a minute apart
+fn post_image<'a>(
+   app_config: &Config,
+   images: &'a HashMap<String, Image>,
+   internal_db: &mut ImageDB,
+   slot: DateTime<FixedOffset>,
+   scheduled_at: Option<DateTime<FixedOffset>>,
+) -> Result<&'a Image, ()> {
+   let posted_at = scheduled_at.unwrap_or_else(|| app_config.now());
+   let image = get_image_to_post(app_config, images, internal_db, posted_at.date_naive())?;
+   let series = get_series_images(images, image);
+   let image = series[0];
    let image_hash = image.get_hash();
 
-   let image_bytes = get_image_data(app_config.get_local_path().as_deref(), &image.location);
-
-   // Check if image data was fetched correctly
-   let Ok(image_bytes) = image_bytes else {
-      let error = image_bytes.unwrap_err();
-      let error_message = match &error {
-         GetImageErrorLevel::Normal(message) => message,
-         GetImageErrorLevel::Critical(message) => message,
-      };
-      app_config.output_message(&format!("{:#}", error_message), MessageLevel::Error, MessageOutput::Stderr);
-
-      //Remove image for critical errors
-      if matches!(error, GetImageErrorLevel::Critical { .. }) {
-         match internal_db.unused.is_empty() {
-            true => {
-               let pos = internal_db.random_deck.iter().position(|hash| hash == &image_hash).unwrap();
-               internal_db.random_deck.remove(pos);
-            }
-            false => {
-               let pos = internal_db.unused.iter().position(|hash| hash == &image_hash).unwrap();
-               internal_db.unused.remove(pos);
-               internal_db.used.push(image_hash.to_owned());
-            }
-         };
-      }
-      return Err(());
-   };
-
    let client = match get_client(Some(&app_config.token)) {
       Ok(client) => client,
       Err(e) => {
@@ -382,55 +454,305 @@ fn post_image<'a>(app_config: &Config, images: &'a HashMap<String, Image>, inter
       }
    };
 
-   let media_id: String = upload_image_to_media_api(&client, app_config, image_bytes, image)?;
+   //Continue partially posted series
+   let (mut posted, mut in_reply_to_id) = match &internal_db.series_progress {
+      Some(progress) if Some(&progress.series) == image.series.as_ref() => (progress.posted, progress.status_id.clone()),
+      _ => (0, None),
+   };
+
+   let (status_visiblity, _) = get_status_visibility(app_config, internal_db);
+
+   for series_image in &series[posted.min(series.len())..] {
+      let idempotency_key = get_idempotency_key(app_config, internal_db, series_image, slot);
+      let scheduled_at = scheduled_at.map(|scheduled_at| scheduled_at + chrono::Duration::minutes(posted as i64));
+      match post_single_image(
+         app_config,
+         &client,
+         series_image,
+         status_visiblity.clone(),
+         in_reply_to_id.as_deref().filter(|_| scheduled_at.is_none()),
+         &idempotency_key,
+         scheduled_at,
+      ) {
+         Ok(status_id) => {
+            match scheduled_at {
+               Some(time) => internal_db.scheduled_statuses.push(ScheduledStatus {
+                  id: status_id,
+                  hash: series_image.get_hash(),
+                  slot,
+                  time,
+               }),
+               None => in_reply_to_id = Some(status_id),
+            }
+            posted += 1;
+            internal_db.idempotency_key = None;
+         }
+         Err(error) => {
+            let error_message = match &error {
+               PostErrorLevel::Retry(message) => message,
+               PostErrorLevel::ImageUnusable(message) => message,
+            };
+            app_config.output_message(&format!("{:#}", error_message), MessageLevel::Error, MessageOutput::Stderr);
+
+            internal_db.series_progress = match &image.series {
+               Some(series) if posted > 0 => {
+                  Some(SeriesProgress { series: series.to_owned(), posted, status_id: in_reply_to_id })
+               }
+               _ => None,
+            };
+
+            //Remove image that can't be posted
+            if matches!(error, PostErrorLevel::ImageUnusable(_)) {
+               mark_series_used(internal_db, &series);
+               internal_db.failed_image = None;
+               internal_db.series_progress = None;
+               internal_db.idempotency_key = None;
+            } else {
+               record_failed_image(internal_db, &image_hash);
+            }
+            return Err(());
+         }
+      }
+   }
+
+   internal_db.series_progress = None;
+   record_posted_image(app_config, internal_db, &series, posted_at);
+
+   Ok(image)
+}
+
+/// Upload images of one entry and create status with them
+/// * `app_config` - Configuration of the bot
+/// * `client` - Client to make requests
+/// * `image` - Image to post
+/// * `visibility` - Visibility of the post
+/// * `in_reply_to_id` - Optional id of status to reply to
+/// * `idempotency_key` - Key for the server to recognize repeated request for the same status
+/// * `scheduled_at` - Optional time to schedule the status on the server for
+///
+/// Returns id of the created status or of the scheduled status
+fn post_single_image(
+   app_config: &Config,
+   client: &Client,
+   image: &Image,
+   visibility: StatusVisibility,
+   in_reply_to_id: Option<&str>,
+   idempotency_key: &str,
+   scheduled_at: Option<DateTime<FixedOffset>>,
+) -> Result<String, PostErrorLevel> {
+   let mut images_bytes = Vec::new();
+   for location in image.location.iter() {
+      images_bytes.push(get_image_data(app_config.get_local_path().as_deref(), location)?);
+   }
 
-   let (status_visiblity, new_vis_sequence) = get_status_visibility(app_config, internal_db);
+   let mut media_ids = Vec::new();
+   for (index, (location, image_bytes)) in image.location.iter().zip(images_bytes).enumerate() {
+      let Ok(media_id) =
+         upload_image_to_media_api(client, app_config, image_bytes, location, image.get_alt(index), image.get_focus(index))
+      else {
+         return Err(PostErrorLevel::Retry(anyhow!("Unable to upload image {}", location)));
+      };
+      media_ids.push(media_id);
+   }
 
-   create_new_status_with_image(&client, app_config, media_id, image, status_visiblity)?;
+   create_new_status_with_image(client, app_config, media_ids, image, visibility, in_reply_to_id, idempotency_key, scheduled_at)
+      .map_err(|_| PostErrorLevel::Retry(anyhow!("Unable to create status for image {}", image.location)))
+}
 
-   //Remove hash from the lists
-   match internal_db.unused.is_empty() {
-      true => {
-         let pos = internal_db.random_deck.iter().position(|hash| hash == &image_hash).unwrap();
-         internal_db.random_deck.remove(pos);
+/// Get idempotency key for status with the image, key of the previous unconfirmed attempt with the same image and slot is reused
+///
+/// New key is saved to the database before the status is created, so it is reused if the bot is stopped before the status is confirmed
+/// * `app_config` - Configuration of the bot
+/// * `internal_db` - Database of images
+/// * `image` - Image to post
+/// * `slot` - Post time the image is posted for
+fn get_idempotency_key(app_config: &Config, internal_db: &mut ImageDB, image: &Image, slot: DateTime<FixedOffset>) -> String {
+   let image_hash = image.get_hash();
+   match &internal_db.idempotency_key {
+      Some(idempotency_key) if idempotency_key.hash == image_hash && idempotency_key.slot == Some(slot) => {
+         idempotency_key.key.to_owned()
       }
-      false => {
-         let pos = internal_db.unused.iter().position(|hash| hash == &image_hash).unwrap();
-         internal_db.unused.remove(pos);
-         internal_db.used.push(image_hash.to_owned());
+      _ => {
+         let key = format!("{:x}", md5::compute(format!("{}-{}", image_hash, slot.to_rfc3339())));
+         internal_db.idempotency_key = Some(IdempotencyKey { hash: image_hash, slot: Some(slot), key: key.to_owned() });
+         save_images_ids(internal_db, app_config);
+         key
       }
+   }
+}
+
+/// Get images of the series the image belongs to in order of image json, only the image itself if it is not in series
+/// * `images` - Hashmap with images
+/// * `image` - Image from the series
+fn get_series_images<'a>(images: &'a HashMap<String, Image>, image: &'a Image) -> Vec<&'a Image> {
+   let Some(series) = &image.series else {
+      return vec![image];
    };
+   let mut series_images: Vec<&Image> = images.values().filter(|other| other.series.as_ref() == Some(series)).collect();
+   series_images.sort_by_key(|series_image| series_image.position);
+   series_images
+}
 
-   internal_db.visiblity_sequence = new_vis_sequence;
+/// Update database of images after the image was posted
+/// * `app_config` - Configuration of the bot
+/// * `internal_db` - Database of images
+/// * `series` - Posted image with the rest of its series
+/// * `posted_at` - Time of the post
+fn record_posted_image(app_config: &Config, internal_db: &mut ImageDB, series: &[&Image], posted_at: DateTime<FixedOffset>) {
+   let image = series[0];
+
+   //Remove hashes from the lists
+   mark_series_used(internal_db, series);
+   for series_image in series {
+      internal_db.last_posted.insert(series_image.get_hash(), posted_at);
+   }
+   internal_db.failed_image = None;
 
-   Ok(image)
+   internal_db.visiblity_sequence = get_status_visibility(app_config, internal_db).1;
+   internal_db.category_sequence = get_next_category_sequence(app_config, internal_db, image);
+}
+
+/// Mark images of the post as used, sequential selection continues after them unless they were queued or pinned to a date
+/// * `internal_db` - Database of images
+/// * `series` - Image with the rest of its series
+fn mark_series_used(internal_db: &mut ImageDB, series: &[&Image]) {
+   let in_sequence =
+      series.iter().all(|series_image| series_image.post_on.is_none() && !internal_db.queue.contains(&series_image.get_hash()));
+   for series_image in series {
+      internal_db.mark_used(&series_image.get_hash());
+   }
+   if in_sequence {
+      internal_db.sequence_last = series.last().map(|series_image| series_image.get_hash());
+   }
+}
+
+/// Count failed attempt to post the image so it can be retried or replaced
+/// * `internal_db` - Database of images
+/// * `image_hash` - Hash of the image that failed to be posted
+fn record_failed_image(internal_db: &mut ImageDB, image_hash: &String) {
+   match &mut internal_db.failed_image {
+      Some(failed_image) if &failed_image.hash == image_hash => failed_image.failures += 1,
+      _ => internal_db.failed_image = Some(FailedImage { hash: image_hash.to_owned(), failures: 1 }),
+   }
 }
 
 /// Select image to post from database of images
 /// * `app_config` - Configuration of the bot
 /// * `images` - Hashmap with images
 /// * `images_db` - Database of images
+/// * `date` - Date of the post, images outside of their validity window are not picked
 fn get_image_to_post<'a>(
    app_config: &Config,
    images: &'a HashMap<String, Image>,
    images_db: &mut ImageDB,
+   date: NaiveDate,
 ) -> Result<&'a Image, ()> {
-   if images_db.used.is_empty() && images_db.unused.is_empty() {
+   if images.is_empty() {
       app_config.panic_message("No image to post contained in image_json file", MessageLevel::Critical);
    }
 
-   let rng = &mut rand::thread_rng();
+   let rng = &mut match (app_config.rng_seed, &images_db.rng_state) {
+      (Some(seed), Some(rng_state)) if rng_state.seed == seed => StdRng::seed_from_u64(rng_state.state),
+      (Some(seed), _) => StdRng::seed_from_u64(seed),
+      (None, _) => StdRng::from_entropy(),
+   };
+
+   //Images pinned to a date are posted only on that date
+   let available = |hash: &String| images.get(hash).is_some_and(|image| image.post_on.is_none() && image.is_available(date));
+
+   //Refill random deck with used images, images outside of their validity window stay in it
+   if !images_db.unused.iter().any(available) && !images_db.random_deck.iter().any(available) {
+      let refill: Vec<String> = images_db.used.iter().filter(|hash| !images_db.random_deck.contains(hash)).cloned().collect();
+      images_db.random_deck.extend(refill);
+      app_config.output_message("Random deck was shuffled", MessageLevel::Debug, MessageOutput::Stdout);
+   }
+
+   //Finish partially posted series before anything else
+   if let Some(progress) = &images_db.series_progress {
+      let series_image = images.values().find(|image| image.series.as_ref() == Some(&progress.series));
+      match series_image {
+         Some(series_image) => return Ok(series_image),
+         None => images_db.series_progress = None,
+      }
+   }
+
+   //Post images pinned to the date at the first post time of the day
+   let mut pinned: Vec<&Image> = images
+      .values()
+      .filter(|image| image.post_on.as_ref().is_some_and(|post_on| post_on.matches(date)))
+      .filter(|image| images_db.last_posted.get(&image.get_hash()).is_none_or(|posted| posted.date_naive() != date))
+      .collect();
+   pinned.sort_by_key(|image| image.position);
+   if let Some(image) = pinned.first() {
+      return Ok(image);
+   }
+
+   //Retry the same image after failed post unless it failed too many times
+   let mut excluded_hash = None;
+   if let Some(failed_image) = &images_db.failed_image {
+      let in_pool = images_db.unused.contains(&failed_image.hash) || images_db.random_deck.contains(&failed_image.hash);
+      match app_config.retry_change_image_after {
+         Some(change_after) if failed_image.failures >= change_after => {
+            app_config.output_message(
+               &format!(
+                  "Picking different image after {} failed attempts with image {}",
+                  failed_image.failures, failed_image.hash
+               ),
+               MessageLevel::Notice,
+               MessageOutput::Stdout,
+            );
+            excluded_hash = Some(failed_image.hash.to_owned());
+         }
+         _ if in_pool && available(&failed_image.hash) => {
+            return Ok(images.get(&failed_image.hash).expect("Image presence was checked"));
+         }
+         _ => {}
+      }
+   }
+
+   //Post queued images before any other
+   let queued = images_db.queue.iter().find(|hash| Some(*hash) != excluded_hash.as_ref() && available(hash));
+   if let Some(hash) = queued {
+      return Ok(images.get(hash).expect("Image presence was checked"));
+   }
 
-   //Get random hash from unused if there is any else from random deck
-   let image_hash = match images_db.unused.is_empty() {
-      true => {
-         if images_db.random_deck.is_empty() {
-            images_db.random_deck.append(&mut images_db.used.to_vec());
-            app_config.output_message("Random deck was shuffled", MessageLevel::Debug, MessageOutput::Stdout);
+   let image_hash = match app_config.selection {
+      Selection::Random | Selection::LeastRecent => {
+         //Get random hash from unused if there is any else from random deck
+         let pool: Vec<&String> = match images_db.unused.iter().any(available) {
+            true => images_db.unused.iter().filter(|hash| available(hash)).collect(),
+            false => images_db.random_deck.iter().filter(|hash| available(hash)).collect(),
+         };
+         let mut candidates: Vec<&String> = pool.iter().filter(|hash| Some(**hash) != excluded_hash.as_ref()).copied().collect();
+         if candidates.is_empty() {
+            candidates = pool;
+         }
+         let candidates = dedup_series(images, candidates);
+         let candidates = filter_repost_cooldown(app_config, images_db, candidates);
+         let candidates = filter_category_rotation(app_config, images, images_db, candidates);
+         let candidates = filter_album_gap(app_config, images, images_db, candidates);
+         match (candidates.is_empty(), app_config.selection == Selection::LeastRecent && !images_db.unused.iter().any(available))
+         {
+            (true, _) => None,
+            (false, true) => Some(get_least_recently_posted(images_db, &candidates).to_owned()),
+            (false, false) => Some(pick_weighted_hash(&candidates, images, rng).to_owned()),
          }
-         images_db.random_deck.get(rng.gen_range(0..images_db.random_deck.len())).unwrap().to_owned()
       }
-      false => images_db.unused.get(rng.gen_range(0..images_db.unused.len())).unwrap().to_owned(),
+      Selection::Sequential | Selection::Reverse => {
+         get_next_sequential_hash(app_config, images, images_db, excluded_hash.as_ref(), available)
+      }
+   };
+
+   //Keep state of seeded generator so the picks continue the same way after restart
+   images_db.rng_state = app_config.rng_seed.map(|seed| RngState { seed, state: rng.gen() });
+
+   let Some(image_hash) = image_hash else {
+      app_config.output_message(
+         &format!("No image can be posted on {}, all images are outside of their validity window", date),
+         MessageLevel::Warning,
+         MessageOutput::Stderr,
+      );
+      return Err(());
    };
 
    //Get image from hash
@@ -447,6 +769,202 @@ fn get_image_to_post<'a>(
    }
 }
 
+/// Keep only the first candidate of every series so the series is picked as one image
+/// * `images` - Hashmap with images
+/// * `candidates` - Hashes of images to pick from
+fn dedup_series<'a>(images: &HashMap<String, Image>, candidates: Vec<&'a String>) -> Vec<&'a String> {
+   let mut seen_series = HashSet::new();
+   candidates
+      .into_iter()
+      .filter(|hash| match images.get(*hash).and_then(|image| image.series.as_ref()) {
+         Some(series) => seen_series.insert(series),
+         None => true,
+      })
+      .collect()
+}
+
+/// Remove candidates that were posted during the repost cooldown
+/// * `app_config` - Configuration of the bot
+/// * `images_db` - Database of images
+/// * `candidates` - Hashes of images to pick from
+///
+/// Keeps only the least recently posted candidate if all of them were posted during the cooldown
+fn filter_repost_cooldown<'a>(app_config: &Config, images_db: &ImageDB, candidates: Vec<&'a String>) -> Vec<&'a String> {
+   let Some(cooldown) = app_config.repost_cooldown else {
+      return candidates;
+   };
+   let cooldown_start = app_config.now() - chrono::Duration::days(cooldown.into());
+   let allowed: Vec<&String> = candidates
+      .iter()
+      .filter(|hash| images_db.last_posted.get(**hash).is_none_or(|posted| *posted <= cooldown_start))
+      .copied()
+      .collect();
+   if !allowed.is_empty() || candidates.is_empty() {
+      return allowed;
+   }
+
+   let least_recent = get_least_recently_posted(images_db, &candidates);
+   app_config.output_message(
+      &format!(
+         "All images to pick from were posted in last {} days, posting the least recently posted image {}",
+         cooldown, least_recent
+      ),
+      MessageLevel::Warning,
+      MessageOutput::Stderr,
+   );
+   vec![least_recent]
+}
+
+/// Get candidate that wasn't posted for the longest time, images never posted go first
+/// * `images_db` - Database of images
+/// * `candidates` - Hashes of images to pick from, can't be empty
+fn get_least_recently_posted<'a>(images_db: &ImageDB, candidates: &[&'a String]) -> &'a String {
+   candidates
+      .iter()
+      .min_by_key(|hash| images_db.last_posted.get(**hash))
+      .expect("Candidates for the image to post can't be empty")
+}
+
+/// Get hash of the next image in order of the image json file
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `images_db` - Database of images
+/// * `excluded_hash` - Hash of image that shouldn't be picked if there is other option
+/// * `available` - Check if image can be posted
+///
+/// Walks the images from the last one taken out of the lists and prefers images that weren't posted yet,
+/// so images appended to the image json file are posted before the order starts over
+fn get_next_sequential_hash(
+   app_config: &Config,
+   images: &HashMap<String, Image>,
+   images_db: &ImageDB,
+   excluded_hash: Option<&String>,
+   available: impl Fn(&String) -> bool,
+) -> Option<String> {
+   let mut ordered: Vec<&Image> = images.values().collect();
+   ordered.sort_by_key(|image| image.position);
+   if app_config.selection == Selection::Reverse {
+      ordered.reverse();
+   }
+
+   //Start right after the last image taken or at the beginning if it is not in image json anymore
+   let start = images_db
+      .sequence_last
+      .as_ref()
+      .and_then(|last| images.get(last))
+      .and_then(|last| ordered.iter().position(|image| image.position == last.position))
+      .map_or(0, |index| index + 1);
+   let walk: Vec<String> = ordered
+      .iter()
+      .cycle()
+      .skip(start)
+      .take(ordered.len())
+      .map(|image| image.get_hash())
+      .filter(|hash| available(hash))
+      .collect();
+
+   let allowed: Vec<&String> = walk.iter().filter(|hash| Some(*hash) != excluded_hash).collect();
+   let allowed = filter_category_rotation(app_config, images, images_db, allowed);
+   allowed
+      .iter()
+      .find(|hash| images_db.unused.contains(hash))
+      .or_else(|| allowed.first())
+      .copied()
+      .or_else(|| walk.first())
+      .cloned()
+}
+
+/// Keep only candidates of the current category in rotation
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `images_db` - Database of images
+/// * `candidates` - Hashes of images to pick from
+///
+/// Categories without any candidate are skipped, candidates are kept unchanged if none of the categories has a candidate
+fn filter_category_rotation<'a>(
+   app_config: &Config,
+   images: &HashMap<String, Image>,
+   images_db: &ImageDB,
+   candidates: Vec<&'a String>,
+) -> Vec<&'a String> {
+   let Some(rotation) = &app_config.category_rotation else {
+      return candidates;
+   };
+   for offset in 0..rotation.len() {
+      let category = &rotation[(images_db.category_sequence + offset) % rotation.len()];
+      let in_category: Vec<&String> = candidates
+         .iter()
+         .filter(|hash| images.get(**hash).and_then(|image| image.category.as_ref()) == Some(category))
+         .copied()
+         .collect();
+      if !in_category.is_empty() {
+         return in_category;
+      }
+   }
+   candidates
+}
+
+/// Remove candidates from albums posted during last posts
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `images_db` - Database of images
+/// * `candidates` - Hashes of images to pick from
+///
+/// Keeps candidates from albums posted the longest time ago if all of them are from recently posted albums
+fn filter_album_gap<'a>(
+   app_config: &Config,
+   images: &HashMap<String, Image>,
+   images_db: &ImageDB,
+   candidates: Vec<&'a String>,
+) -> Vec<&'a String> {
+   let Some(gap) = app_config.min_album_gap else {
+      return candidates;
+   };
+
+   //Albums of the last posts from the most recent one
+   let mut history: Vec<(&String, &DateTime<FixedOffset>)> = images_db.last_posted.iter().collect();
+   history.sort_by(|a, b| b.1.cmp(a.1));
+   let recent_albums: Vec<String> =
+      history.iter().take(gap).filter_map(|(hash, _)| images.get(*hash)).map(|image| image.get_album()).collect();
+
+   //Candidates with album not posted recently get the highest rank
+   let rank = |hash: &String| {
+      images.get(hash).and_then(|image| recent_albums.iter().position(|album| *album == image.get_album())).unwrap_or(usize::MAX)
+   };
+   let Some(best_rank) = candidates.iter().map(|hash| rank(hash)).max() else {
+      return candidates;
+   };
+   candidates.into_iter().filter(|hash| rank(hash) == best_rank).collect()
+}
+
+/// Get position in rotation of categories following the category of posted image
+/// * `app_config` - Configuration of the bot
+/// * `internal_db` - Database of images
+/// * `image` - Posted image
+fn get_next_category_sequence(app_config: &Config, internal_db: &ImageDB, image: &Image) -> usize {
+   let Some(rotation) = &app_config.category_rotation else {
+      return 0;
+   };
+   let offset = (0..rotation.len())
+      .find(|offset| image.category.as_ref() == Some(&rotation[(internal_db.category_sequence + offset) % rotation.len()]))
+      .unwrap_or(0);
+   (internal_db.category_sequence + offset + 1) % rotation.len()
+}
+
+/// Pick hash of image from candidates with probability given by weight of the image
+/// * `candidates` - Hashes of images to pick from
+/// * `images` - Images from image json
+/// * `rng` - Random number generator
+///
+/// Falls back to uniform pick when no candidate has positive weight
+fn pick_weighted_hash<'a>(candidates: &[&'a String], images: &HashMap<String, Image>, rng: &mut impl Rng) -> &'a String {
+   let weights = candidates.iter().map(|hash| images.get(*hash).map_or(1.0, |image| image.get_weight()));
+   match WeightedIndex::new(weights) {
+      Ok(distribution) => candidates[distribution.sample(rng)],
+      Err(_) => candidates[rng.gen_range(0..candidates.len())],
+   }
+}
+
 /// Get status visibility based on the configuration and internal database
 /// * `app_config` - Configuration of the bot
 /// * `internal_db` - Database of images
@@ -463,6 +981,270 @@ fn get_status_visibility(app_config: &Config, internal_db: &ImageDB) -> (StatusV
    }
 }
 
+/// Plan next attempt to post the failed slot with exponential backoff or give up if there were too many attempts
+/// * `app_config` - Configuration of the bot
+/// * `internal_db` - Database with the retry state
+/// * `slot` - Slot for which the posting failed
+fn schedule_retry(app_config: &Config, internal_db: &mut ImageDB, slot: DateTime<FixedOffset>) {
+   let attempts = match &internal_db.retry {
+      Some(retry) if retry.slot == slot => retry.attempts + 1,
+      // Retry of the older slot is replaced by the new one
+      Some(retry) => {
+         abandon_slot(internal_db, retry.slot);
+         1
+      }
+      None => 1,
+   };
+
+   if let Some(max_attempts) = app_config.retry_max_attempts {
+      if attempts >= max_attempts {
+         app_config.output_message(
+            &format!("Giving up posting for slot {} after {} failed attempts", slot, attempts),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         internal_db.retry = None;
+         abandon_slot(internal_db, slot);
+         return;
+      }
+   }
+
+   let delay = get_retry_delay(app_config, attempts);
+   let next_attempt = app_config.now() + delay;
+   app_config.output_message(
+      &format!("Posting failed {} times for slot {}, next attempt at {}", attempts, slot, next_attempt),
+      MessageLevel::Notice,
+      MessageOutput::Stdout,
+   );
+   internal_db.retry = Some(RetryState { slot, attempts, next_attempt });
+}
+
+/// Remember slot that won't be posted anymore so it isn't posted as missed slot later, idempotency key of its unconfirmed status is dropped
+/// * `internal_db` - Database with the abandoned slot
+/// * `slot` - Slot for which the posting was given up
+fn abandon_slot(internal_db: &mut ImageDB, slot: DateTime<FixedOffset>) {
+   internal_db.idempotency_key = None;
+   internal_db.abandoned_slot = Some(internal_db.abandoned_slot.map_or(slot, |abandoned_slot| abandoned_slot.max(slot)));
+}
+
+/// Get delay before the next attempt after given number of failed attempts
+/// * `app_config` - Configuration of the bot
+/// * `attempts` - Number of failed attempts
+fn get_retry_delay(app_config: &Config, attempts: u32) -> chrono::Duration {
+   let delay = app_config.retry_time as f64 * app_config.retry_multiplier.max(1.0).powi(attempts.saturating_sub(1) as i32);
+   let max_delay = app_config.retry_max_delay.map_or(f64::MAX, |max_delay| max_delay.num_seconds() as f64);
+
+   // Limit to one year to not overflow when the delay is unlimited
+   chrono::Duration::seconds(delay.min(max_delay).min(366.0 * 24.0 * 3600.0) as i64)
+}
+
+/// Read database of images from the file
+/// * `app_config` - Configuration of the bot
+///
+/// Returns None if the file doesn't exist
+fn read_internal_database(app_config: &Config) -> Option<Result<ImageDB, serde_json::Error>> {
+   let file = File::open(app_config.get_internal_database_path()).ok()?;
+   Some(serde_json::from_reader(BufReader::new(file)))
+}
+
+/// Add and remove images from the queue of images posted next and print the queue.
+///
+/// Bot can be running meanwhile, so only the queue of the database stored on disk is changed
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `internal_db` - Database of images
+/// * `enqueue` - Locations or hashes of images to add to the queue
+/// * `dequeue` - Locations or hashes of images to remove from the queue
+fn manage_queue(
+   app_config: &Config,
+   images: &HashMap<String, Image>,
+   internal_db: &mut ImageDB,
+   enqueue: &[String],
+   dequeue: &[String],
+) {
+   let find_hash = |image: &String| match images.contains_key(image) {
+      true => image.to_owned(),
+      false => match images.iter().find(|(_, source)| source.location.contains(image)) {
+         Some((hash, _)) => hash.to_owned(),
+         None => {
+            app_config.panic_message(&format!("Image {} not found by location or hash in image json", image), MessageLevel::Error)
+         }
+      },
+   };
+   let enqueue: Vec<(&String, String)> = enqueue.iter().map(|image| (image, find_hash(image))).collect();
+   let dequeue: Vec<(&String, String)> = dequeue.iter().map(|image| (image, find_hash(image))).collect();
+   let changed = !enqueue.is_empty() || !dequeue.is_empty();
+
+   //Reread the database right before writing so changes made by running bot are not overwritten
+   let mut stored_db = match read_internal_database(app_config) {
+      Some(Ok(stored_db)) => stored_db,
+      Some(Err(e)) => {
+         app_config.panic_message(&format!("Unable to parse internal_database file.\nError: {:#}", e), MessageLevel::Critical);
+      }
+      None => internal_db.clone(),
+   };
+   let missing_queued: Vec<String> = stored_db.queue.iter().filter(|hash| !images.contains_key(*hash)).cloned().collect();
+   for hash in &missing_queued {
+      stored_db.dequeue(hash);
+   }
+
+   for (image, hash) in enqueue {
+      if !stored_db.enqueue(&hash) {
+         app_config.output_message(&format!("Image {} is already in queue", image), MessageLevel::Notice, MessageOutput::Stdout);
+      }
+   }
+   for (image, hash) in dequeue {
+      if !stored_db.dequeue(&hash) {
+         app_config.output_message(&format!("Image {} is not in queue", image), MessageLevel::Notice, MessageOutput::Stdout)
+      }
+   }
+   if changed {
+      save_images_ids(&mut stored_db, app_config);
+   }
+   internal_db.queue = stored_db.queue;
+
+   if internal_db.queue.is_empty() {
+      app_config.output_message("Queue is empty", MessageLevel::Info, MessageOutput::Stdout);
+   }
+   for (index, hash) in internal_db.queue.iter().enumerate() {
+      let location = images.get(hash).map_or(String::new(), |image| image.location.to_string());
+      app_config.output_message(&format!("{}. {} ({})", index + 1, location, hash), MessageLevel::Info, MessageOutput::Stdout);
+   }
+}
+
+/// Print the next posts without posting them or changing the database
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `internal_db` - Database of images
+/// * `count` - Number of posts to show
+fn preview_next_posts(app_config: &Config, images: &HashMap<String, Image>, internal_db: &ImageDB, count: usize) {
+   if app_config.rng_seed.is_none() && matches!(app_config.selection, Selection::Random | Selection::LeastRecent) {
+      app_config.output_message(
+         "Images are picked at random, set rng_seed to get the same images as will be posted",
+         MessageLevel::Notice,
+         MessageOutput::Stdout,
+      );
+   }
+
+   let mut preview_db = internal_db.clone();
+   let mut next_post = plan_next_post(app_config, &mut preview_db);
+   for _ in 0..count {
+      let Ok(image) = get_image_to_post(app_config, images, &mut preview_db, next_post.time.date_naive()) else {
+         break;
+      };
+      let series = get_series_images(images, image);
+      let locations: Vec<String> = series.iter().map(|series_image| series_image.location.to_string()).collect();
+      app_config.output_message(&format!("{}: {}", next_post, locations.join(" -> ")), MessageLevel::Info, MessageOutput::Stdout);
+
+      record_posted_image(app_config, &mut preview_db, &series, next_post.time);
+      preview_db.last_post = Some(next_post);
+      next_post = plan_next_post(app_config, &mut preview_db);
+   }
+}
+
+/// Keep the next posts scheduled on the server so the bot doesn't have to run at post times
+/// * `app_config` - Configuration of the bot
+/// * `images` - Hashmap with images
+/// * `internal_db` - Database of images
+/// * `count` - Number of posts to keep scheduled on the server
+fn schedule_next_posts(
+   app_config: &Config,
+   images: &HashMap<String, Image>,
+   internal_db: &mut ImageDB,
+   count: usize,
+) -> Result<(), ()> {
+   let client = match get_client(Some(&app_config.token)) {
+      Ok(client) => client,
+      Err(e) => {
+         app_config.output_message(
+            &format!("Unable to initialize client to schedule images: {:#}", e),
+            MessageLevel::Error,
+            MessageOutput::Stderr,
+         );
+         return Err(());
+      }
+   };
+
+   reconcile_scheduled_statuses(app_config, &client, internal_db)?;
+
+   let mut pending_slots: Vec<DateTime<FixedOffset>> =
+      internal_db.scheduled_statuses.iter().map(|scheduled_status| scheduled_status.slot).collect();
+   pending_slots.dedup();
+   for _ in pending_slots.len()..count {
+      // Server accepts only statuses scheduled at least 5 minutes ahead
+      let mut next_post = plan_next_post(app_config, internal_db);
+      next_post.time = next_post.time.max(app_config.now() + chrono::Duration::minutes(MIN_SCHEDULE_AHEAD_MINUTES));
+
+      let image = post_image(app_config, images, internal_db, next_post.slot, Some(next_post.time))?;
+      app_config.output_message(
+         &format!("Image {} scheduled for {}", image.location, next_post),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+      internal_db.last_post = Some(next_post);
+   }
+
+   if let Some(last_scheduled) = internal_db.scheduled_statuses.last() {
+      app_config.output_message(
+         &format!(
+            "{} statuses are scheduled on the server, the last one at {}",
+            internal_db.scheduled_statuses.len(),
+            last_scheduled.time
+         ),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+   }
+   Ok(())
+}
+
+/// Compare statuses scheduled by the bot with statuses scheduled on the server.
+///
+/// Statuses missing on the server after their time were published, statuses missing before their time were deleted and
+/// their images are returned to unused images
+/// * `app_config` - Configuration of the bot
+/// * `client` - Client to make requests
+/// * `internal_db` - Database of images
+fn reconcile_scheduled_statuses(app_config: &Config, client: &Client, internal_db: &mut ImageDB) -> Result<(), ()> {
+   if internal_db.scheduled_statuses.is_empty() {
+      return Ok(());
+   }
+   let server_ids = get_scheduled_status_ids(client, app_config)?;
+
+   let now = app_config.now();
+   let (pending, finished): (Vec<ScheduledStatus>, Vec<ScheduledStatus>) =
+      internal_db.scheduled_statuses.drain(..).partition(|scheduled_status| server_ids.contains(&scheduled_status.id));
+   internal_db.scheduled_statuses = pending;
+   let (published, deleted): (Vec<ScheduledStatus>, Vec<ScheduledStatus>) =
+      finished.into_iter().partition(|scheduled_status| scheduled_status.time <= now);
+
+   if !published.is_empty() {
+      app_config.output_message(
+         &format!("{} scheduled statuses were published", published.len()),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+   }
+   if !deleted.is_empty() {
+      for scheduled_status in &deleted {
+         internal_db.mark_unused(&scheduled_status.hash);
+      }
+      // Schedule continues from the last status that is still scheduled
+      internal_db.last_post = match internal_db.scheduled_statuses.last() {
+         Some(scheduled_status) => Some(ScheduledPost { slot: scheduled_status.slot, time: scheduled_status.time }),
+         None => {
+            published.last().map(|scheduled_status| ScheduledPost { slot: scheduled_status.slot, time: scheduled_status.time })
+         }
+      };
+      app_config.output_message(
+         &format!("{} scheduled statuses were deleted from the server, their images will be posted again", deleted.len()),
+         MessageLevel::Notice,
+         MessageOutput::Stdout,
+      );
+   }
+   Ok(())
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about = "Mastodon bot to post remotely hosted photos daily at set times")]
 struct Args {
@@ -478,6 +1260,33 @@ struct Args {
    #[arg(short = 'C', long, help = "Test if the configuration and images are correct", action)]
    check: bool,
 
+   #[arg(
+      short = 'P',
+      long,
+      value_name = "COUNT",
+      num_args = 0..=1,
+      default_missing_value = "1",
+      help = "Show the next images to post and exit without posting"
+   )]
+   preview: Option<usize>,
+
+   #[arg(long, value_name = "IMAGE", help = "Add image by location or hash to the queue of images posted next and exit")]
+   enqueue: Vec<String>,
+
+   #[arg(long, value_name = "IMAGE", help = "Remove image by location or hash from the queue of images posted next and exit")]
+   dequeue: Vec<String>,
+
+   #[arg(long, action, help = "List the queue of images posted next and exit")]
+   queue: bool,
+
+   #[arg(
+      short = 'S',
+      long,
+      value_name = "COUNT",
+      help = "Keep the next COUNT posts scheduled on the server and exit, to run the bot periodically from cron"
+   )]
+   schedule: Option<usize>,
+
    #[arg(trailing_var_arg = true, hide = true)]
    config_old: Vec<String>,
 }
@@ -526,8 +1335,6 @@ fn main() {
       }
    };
 
-   app_config.times.sort_unstable();
-
    app_config.config_path = config_path;
 
    if args.systemd {
@@ -540,22 +1347,26 @@ fn main() {
    }
 
    //Load used and unused list of images
-   let mut internal_db = match File::open(app_config.get_internal_database_path()) {
-      Ok(file) => {
-         let reader = BufReader::new(file);
-         match serde_json::from_reader(reader) {
-            Ok(res) => res,
-            Err(e) => {
-               app_config
-                  .panic_message(&format!("Unable to parse internal_database file.\nError: {:#}", e), MessageLevel::Critical);
-            }
-         }
+   let mut internal_db = match read_internal_database(&app_config) {
+      Some(Ok(internal_db)) => internal_db,
+      Some(Err(e)) => {
+         app_config.panic_message(&format!("Unable to parse internal_database file.\nError: {:#}", e), MessageLevel::Critical);
       }
-      Err(_) => ImageDB { used: Vec::new(), unused: Vec::new(), random_deck: Vec::new(), visiblity_sequence: 0 },
+      None => ImageDB::default(),
    };
 
-   if app_config.times.is_empty() {
-      app_config.panic_message("Config has to contain at least one post time", MessageLevel::Critical);
+   match (app_config.interval.is_some(), app_config.times.is_empty()) {
+      (true, false) => {
+         app_config.panic_message("Config can't contain both times and interval, use only one of them", MessageLevel::Critical)
+      }
+      (false, true) => {
+         app_config.panic_message("Config has to contain at least one post time not tied to date", MessageLevel::Critical)
+      }
+      _ => {}
+   }
+
+   if let Err(error) = check_jitter(&app_config) {
+      app_config.panic_message(&error, MessageLevel::Critical)
    }
 
    //Check for images in image json
@@ -566,6 +1377,16 @@ fn main() {
       }
    };
 
+   if !args.enqueue.is_empty() || !args.dequeue.is_empty() || args.queue {
+      manage_queue(&app_config, &images, &mut internal_db, &args.enqueue, &args.dequeue);
+      exit(0);
+   }
+
+   if let Some(count) = args.preview {
+      preview_next_posts(&app_config, &images, &internal_db, count);
+      exit(0);
+   }
+
    // Run checks
    let check = api::check_connection(&app_config);
    if args.check {
@@ -592,11 +1413,23 @@ fn main() {
 
    save_images_ids(&mut internal_db, &app_config);
 
+   if let Some(count) = args.schedule {
+      let scheduled = schedule_next_posts(&app_config, &images, &mut internal_db, count);
+      save_images_ids(&mut internal_db, &app_config);
+      if scheduled.is_err() {
+         app_config.panic_message("Unable to schedule all posts, the rest is scheduled on the next run", MessageLevel::Error);
+      }
+      exit(0);
+   }
+
    if args.now {
-      let image = post_image(&app_config, &images, &mut internal_db);
+      let now = app_config.now();
+      let image = post_image(&app_config, &images, &mut internal_db, now, None);
       if let Ok(image) = image {
+         // Post made with --now covers slots missed till now, schedule continues after it
+         internal_db.last_post = Some(ScheduledPost { slot: now, time: now });
          app_config.output_message(
-            &format!("Image {} posted with --now at {}", image.location, Local::now()),
+            &format!("Image {} posted with --now at {}", image.location, app_config.now()),
             MessageLevel::Info,
             MessageOutput::Stdout,
          );
@@ -605,39 +1438,63 @@ fn main() {
       }
    }
 
-   // Register handler for SIGUSR1 signal to reload config on Unix systems
-   let reload_signal = Arc::new(AtomicBool::new(false));
-   #[cfg(not(windows))]
+   // Register handler for SIGUSR1 signal to reload config on Unix systems, signals are forwarded to the main loop through channel
+   #[cfg(unix)]
+   let (reload_sender, reload_receiver) = mpsc::channel::<()>();
+   #[cfg(unix)]
    {
-      if let Err(error) = signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&reload_signal)) {
-         app_config.output_message(
-            &format!("Unable to register signal handler for config reload: {:#}", error),
-            MessageLevel::Error,
-            MessageOutput::Stderr,
-         );
+      match signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1]) {
+         Ok(mut signals) => {
+            let reload_sender = reload_sender.clone();
+            thread::spawn(move || {
+               for _ in signals.forever() {
+                  if reload_sender.send(()).is_err() {
+                     break;
+                  }
+               }
+            });
+         }
+         Err(error) => {
+            app_config.output_message(
+               &format!("Unable to register signal handler for config reload: {:#}", error),
+               MessageLevel::Error,
+               MessageOutput::Stderr,
+            );
+         }
       }
    }
 
    //Calculate next time for post and json refresh
-   let current_time = Local::now();
-   let mut next_time = get_next_post_time(current_time, &app_config);
+   let mut next_post = plan_next_post(&app_config, &mut internal_db);
+   save_images_ids(&mut internal_db, &app_config);
    let mut image_config_refresh_time = Instant::now() + time::Duration::from_secs(60 * 30);
 
-   app_config.output_message(&format!("Next image will be at {}", next_time), MessageLevel::Info, MessageOutput::Stdout);
+   app_config.output_message(&format!("Next image will be at {}", next_post), MessageLevel::Info, MessageOutput::Stdout);
+   if let Some((image, date)) = get_next_pinned_image(&images, &internal_db, app_config.now().date_naive()) {
+      app_config.output_message(
+         &format!("Next pinned image {} will be posted on {}", image.location, date),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+   }
    app_config.output_message(
       &format!("{}/{} images left", internal_db.unused.len(), internal_db.unused.len() + internal_db.used.len()),
       MessageLevel::Info,
       MessageOutput::Stdout,
    );
 
-   let mut failed_to_post = false;
-   let mut failed_to_post_time = Instant::now();
+   let mut reload_requested = false;
 
    loop {
+      //Queue can be changed from command line while the bot is running
+      if let Some(Ok(stored_db)) = read_internal_database(&app_config) {
+         internal_db.merge_queue(stored_db.queue);
+      }
+
       //Check if there are changes in image json
-      if image_config_refresh_time < Instant::now() || reload_signal.load(std::sync::atomic::Ordering::Relaxed) {
+      if image_config_refresh_time < Instant::now() || reload_requested {
          image_config_refresh_time = Instant::now() + time::Duration::from_secs(60 * 30); // Reload images every 30 minutes
-         reload_signal.store(false, std::sync::atomic::Ordering::Relaxed);
+         reload_requested = false;
          images = match load_image_paths(&app_config, &mut internal_db, Some(&images)) {
             Ok(images_new) => images_new,
             Err(e) => {
@@ -653,15 +1510,27 @@ fn main() {
          save_images_ids(&mut internal_db, &app_config);
       }
 
+      // Plan again when post is due as slots could be missed while the system was suspended
+      if next_post.time < app_config.now() {
+         next_post = plan_next_post(&app_config, &mut internal_db);
+      }
+
       //Check if it's time to post new image or retry posting image
-      if next_time < Local::now() || (failed_to_post && (Instant::now() - failed_to_post_time).as_secs() > app_config.retry_time)
-      {
-         let image = post_image(&app_config, &images, &mut internal_db);
-         next_time = get_next_post_time(next_time, &app_config);
+      let post_due = next_post.time < app_config.now();
+      let retry_slot = internal_db.retry.as_ref().filter(|retry| retry.next_attempt < app_config.now()).map(|retry| retry.slot);
+      if post_due || retry_slot.is_some() {
+         let slot = if post_due { next_post.slot } else { retry_slot.expect("Retry slot was checked") };
+         let image = post_image(&app_config, &images, &mut internal_db, slot, None);
 
          if let Ok(image) = image {
+            // Retried slot can be older than slot that was posted meanwhile
+            let slot = internal_db.last_post.as_ref().map_or(slot, |last_post| last_post.slot.max(slot));
+            internal_db.last_post = Some(ScheduledPost { slot, time: app_config.now() });
+            internal_db.retry = None;
+            next_post = plan_next_post(&app_config, &mut internal_db);
+
             app_config.output_message(
-               &format!("Image {} posted at {}, next at {}", image.location, Local::now(), next_time),
+               &format!("Image {} posted at {}, next at {}", image.location, app_config.now(), next_post),
                MessageLevel::Info,
                MessageOutput::Stdout,
             );
@@ -671,7 +1540,7 @@ fn main() {
                MessageOutput::Stdout,
             );
             app_config.output_message(
-               &format!("Image alt text: {}", image.alt.as_deref().unwrap_or_default()),
+               &format!("Image alt text: {}", image.alt.as_ref().map(|alt| alt.to_string()).unwrap_or_default()),
                MessageLevel::Info,
                MessageOutput::Stdout,
             );
@@ -681,15 +1550,176 @@ fn main() {
                MessageOutput::Stdout,
             );
 
-            failed_to_post = false;
             save_images_ids(&mut internal_db, &app_config);
          } else {
-            failed_to_post = true;
-            failed_to_post_time = Instant::now();
+            schedule_retry(&app_config, &mut internal_db, slot);
+
+            // Move schedule after the failed slot, retry doesn't move the schedule
+            if post_due {
+               next_post = get_next_post(slot.max(app_config.now()), &app_config, &mut internal_db);
+            }
+            save_images_ids(&mut internal_db, &app_config);
          }
       }
 
-      //Sleep till next check
-      thread::sleep(time::Duration::from_secs(30));
+      //Sleep till the earliest deadline or till the reload signal arrives, deadline is checked again after every wakeup
+      let mut deadline = next_post.time;
+      if let Some(retry) = &internal_db.retry {
+         deadline = deadline.min(retry.next_attempt);
+      }
+      let sleep_time = (deadline - app_config.now())
+         .to_std()
+         .unwrap_or_default()
+         .min(image_config_refresh_time.saturating_duration_since(Instant::now()))
+         .min(time::Duration::from_secs(MAX_SLEEP_SECONDS));
+      #[cfg(unix)]
+      match reload_receiver.recv_timeout(sleep_time) {
+         Ok(()) => reload_requested = true,
+         Err(RecvTimeoutError::Timeout) => {}
+         Err(RecvTimeoutError::Disconnected) => unreachable!("Sender for reload signal is owned by main"),
+      }
+      #[cfg(not(unix))]
+      thread::sleep(sleep_time);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::structures::test_config;
+
+   /// Images 1.jpg to 6.jpg with database where all of them except the pinned ones are unused
+   fn images(extra: &[(usize, serde_json::Value)]) -> (HashMap<String, Image>, ImageDB) {
+      let mut images_json: Vec<serde_json::Value> =
+         (1..=6).map(|index| serde_json::json!({"location": format!("{}.jpg", index)})).collect();
+      for (index, fields) in extra {
+         images_json[*index - 1]
+            .as_object_mut()
+            .expect("image is object")
+            .extend(fields.as_object().expect("fields are object").clone());
+      }
+      let parsed_images: Vec<Image> = serde_json::from_value(serde_json::Value::Array(images_json)).expect("valid images");
+      let unused = parsed_images.iter().filter(|image| image.post_on.is_none()).map(Image::get_hash).collect();
+      let internal_db = ImageDB { unused, ..Default::default() };
+      let images =
+         parsed_images.into_iter().enumerate().map(|(position, image)| (image.get_hash(), Image { position, ..image })).collect();
+      (images, internal_db)
+   }
+
+   fn pick_sequence(config: &Config, images: &HashMap<String, Image>, internal_db: &mut ImageDB, count: usize) -> Vec<String> {
+      let posted_at = DateTime::parse_from_rfc3339("2026-10-16T08:00:00+00:00").expect("valid time");
+      (0..count)
+         .map(|_| {
+            let image = get_image_to_post(config, images, internal_db, posted_at.date_naive()).expect("image to post");
+            let series = get_series_images(images, image);
+            record_posted_image(config, internal_db, &series, posted_at);
+            image.location.to_string()
+         })
+         .collect()
+   }
+
+   #[test]
+   fn seeded_picks_repeat() {
+      let config = test_config(serde_json::json!({"rng_seed": 42}));
+      let (images, internal_db) = images(&[]);
+      let first = pick_sequence(&config, &images, &mut internal_db.clone(), 10);
+      let second = pick_sequence(&config, &images, &mut internal_db.clone(), 10);
+      assert_eq!(first, second);
+
+      // New images are posted before any image is repeated
+      let mut new_images = first[..6].to_vec();
+      new_images.sort();
+      assert_eq!(new_images, ["1.jpg", "2.jpg", "3.jpg", "4.jpg", "5.jpg", "6.jpg"]);
+   }
+
+   #[test]
+   fn seeded_picks_continue_after_restart() {
+      let config = test_config(serde_json::json!({"rng_seed": 7}));
+      let (images, internal_db) = images(&[]);
+      let uninterrupted = pick_sequence(&config, &images, &mut internal_db.clone(), 10);
+
+      let mut restarted_db = internal_db;
+      let mut restarted = pick_sequence(&config, &images, &mut restarted_db, 4);
+      let mut restarted_db: ImageDB = serde_json::from_str(&serde_json::to_string(&restarted_db).expect("database serializes"))
+         .expect("database deserializes");
+      restarted.extend(pick_sequence(&config, &images, &mut restarted_db, 6));
+      assert_eq!(uninterrupted, restarted);
+   }
+
+   #[test]
+   fn sequential_picks_follow_image_json() {
+      let config = test_config(serde_json::json!({"selection": "sequential"}));
+      let (images, mut internal_db) = images(&[]);
+      let picks = pick_sequence(&config, &images, &mut internal_db, 8);
+      assert_eq!(picks, ["1.jpg", "2.jpg", "3.jpg", "4.jpg", "5.jpg", "6.jpg", "1.jpg", "2.jpg"]);
+   }
+
+   #[test]
+   fn queued_image_keeps_sequential_order() {
+      let config = test_config(serde_json::json!({"selection": "sequential"}));
+      let (images, mut internal_db) = images(&[]);
+      internal_db.queue.push(Image::get_hash(images.values().find(|image| image.position == 3).expect("image 4.jpg")));
+      let picks = pick_sequence(&config, &images, &mut internal_db, 6);
+      assert_eq!(picks, ["4.jpg", "1.jpg", "2.jpg", "3.jpg", "5.jpg", "6.jpg"]);
+   }
+
+   #[test]
+   fn pinned_image_keeps_sequential_order() {
+      let config = test_config(serde_json::json!({"selection": "sequential"}));
+      let (images, mut internal_db) = images(&[(4, serde_json::json!({"post_on": "10-16"}))]);
+      let picks = pick_sequence(&config, &images, &mut internal_db, 6);
+      assert_eq!(picks, ["4.jpg", "1.jpg", "2.jpg", "3.jpg", "5.jpg", "6.jpg"]);
+   }
+
+   #[test]
+   fn least_recent_ignores_unused_images_outside_of_validity_window() {
+      let config = test_config(serde_json::json!({"selection": "least_recent"}));
+      let (images, mut internal_db) = images(&[(6, serde_json::json!({"not_before": "2027-01-01"}))]);
+      let start = DateTime::parse_from_rfc3339("2026-10-16T08:00:00+00:00").expect("valid time");
+      let picks: Vec<String> = (0..10)
+         .map(|hour| {
+            let posted_at = start + chrono::Duration::hours(hour);
+            let image = get_image_to_post(&config, &images, &mut internal_db, posted_at.date_naive()).expect("image to post");
+            record_posted_image(&config, &mut internal_db, &get_series_images(&images, image), posted_at);
+            image.location.to_string()
+         })
+         .collect();
+      assert_eq!(picks[5..], picks[..5]);
+      assert!(!picks.contains(&"6.jpg".to_string()));
+   }
+
+   #[test]
+   fn deck_refill_keeps_images_outside_of_validity_window() {
+      let config = test_config(serde_json::json!({}));
+      let (images, mut internal_db) = images(&[(1, serde_json::json!({"not_before": "2027-01-01"}))]);
+      let unavailable = Image::get_hash(images.values().find(|image| image.position == 0).expect("image 1.jpg"));
+      internal_db.used = std::mem::take(&mut internal_db.unused);
+      internal_db.random_deck = vec![unavailable.to_owned()];
+      pick_sequence(&config, &images, &mut internal_db, 1);
+      assert!(internal_db.random_deck.contains(&unavailable));
+      assert_eq!(internal_db.random_deck.len(), 5);
+   }
+
+   #[test]
+   fn pinned_images_are_not_in_unused_images() {
+      let path = std::env::temp_dir().join(format!("vulpes_porto_pinned_test_{}.json", std::process::id()));
+      let images_json = serde_json::json!([{"location": "1.jpg"}, {"location": "2.jpg", "post_on": "12-24"}]);
+      std::fs::write(&path, images_json.to_string()).expect("image json written");
+      let config = test_config(serde_json::json!({"image_json": path.to_str().expect("utf-8 path")}));
+      let mut internal_db = ImageDB::default();
+      let images = load_image_paths(&config, &mut internal_db, None).expect("images load");
+
+      // Pinned image stored in unused images by older version is removed
+      let (pinned_hash, _) = images.iter().find(|(_, image)| image.post_on.is_some()).expect("pinned image");
+      internal_db.unused.push(pinned_hash.to_owned());
+      let images = load_image_paths(&config, &mut internal_db, Some(&images)).expect("images load");
+      std::fs::remove_file(&path).expect("image json removed");
+      assert_eq!(internal_db.unused.len(), 1);
+      assert!(!internal_db.contains(pinned_hash));
+
+      let today = NaiveDate::from_ymd_opt(2026, 10, 16).expect("valid date");
+      let (image, date) = get_next_pinned_image(&images, &internal_db, today).expect("pinned image");
+      assert!(image.location.contains("2.jpg"));
+      assert_eq!(date, NaiveDate::from_ymd_opt(2026, 12, 24).expect("valid date"));
    }
 }
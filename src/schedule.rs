@@ -0,0 +1,545 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, NaiveTime, Offset, TimeZone, Timelike};
+use rand::Rng;
+
+use crate::structures::{Config, ImageDB, MessageLevel, MessageOutput, MissedSlotsPolicy, PostTimeKind, ScheduledPost};
+
+/// How far into the future to search for post time before giving up (covers leap days in cron expressions)
+const MAX_SEARCH_DAYS: i64 = 366 * 8;
+
+/// Default gap between posts of missed slots with the "all" policy
+const DEFAULT_MISSED_SLOTS_GAP_MINUTES: i64 = 10;
+
+/// Slot is considered missed only if its time passed by more than this tolerance
+const MISSED_SLOT_TOLERANCE_MINUTES: i64 = 5;
+
+/// How many times to roll the jitter before posting at the slot itself when jittered time isn't allowed
+const MAX_JITTER_ROLLS: usize = 20;
+
+/// How many days of post times are checked for jitter windows overlapping each other (covers weekly time tables)
+const JITTER_CHECK_DAYS: i64 = 8;
+
+/// Check that jitter windows of consecutive slots don't overlap, so every post is in order and apart from the previous one
+/// * `config` - Configuration of the bot
+pub fn check_jitter(config: &Config) -> Result<(), String> {
+   let window = |jitter: Option<Duration>| jitter.or(config.jitter).unwrap_or_else(Duration::zero);
+   if let Some(interval) = config.interval {
+      return match window(None) * 2 < interval {
+         true => Ok(()),
+         false => Err("Jitter has to be smaller than half of the interval".to_string()),
+      };
+   }
+
+   let check_until = config.now() + Duration::days(JITTER_CHECK_DAYS);
+   let (mut slot, mut jitter) = get_next_post_time(config.now(), config);
+   while slot < check_until {
+      let (next_slot, next_jitter) = get_next_post_time(slot, config);
+      if window(jitter) + window(next_jitter) >= next_slot - slot {
+         return Err(format!(
+            "Jitter windows of post times {} and {} overlap, jitter has to be smaller than half of the gap between post times",
+            slot, next_slot
+         ));
+      }
+      (slot, jitter) = (next_slot, next_jitter);
+   }
+   Ok(())
+}
+
+/// Plan next post after the last posted slot from the internal database.
+///
+/// Slots missed since the last post (bot wasn't running or system was suspended) are handled by the missed slots policy,
+/// slot that is being retried is left to the retry
+/// * `config` - Configuration of the bot
+/// * `internal_db` - Database with the last posted slot
+pub fn plan_next_post(config: &Config, internal_db: &mut ImageDB) -> ScheduledPost {
+   let now = config.now();
+   let now = now.with_nanosecond(0).unwrap_or(now);
+   let last_post = internal_db.last_post.clone();
+
+   // Search from the last posted slot or from the planned slot if nothing was posted yet
+   let search_from = match (&last_post, &internal_db.next_post) {
+      (Some(last_post), _) => last_post.slot,
+      (None, Some(next_post)) => (next_post.slot - Duration::seconds(1)).min(now),
+      (None, None) => now,
+   };
+   // Slot waiting for retry and slots given up after failed attempts aren't missed
+   let search_from = [internal_db.retry.as_ref().map(|retry| retry.slot), internal_db.abandoned_slot]
+      .into_iter()
+      .flatten()
+      .fold(search_from, DateTime::max);
+
+   // Collect slots that passed since the last post
+   let mut missed = Vec::new();
+   let (mut slot, mut window) = get_next_allowed_slot(search_from, config, internal_db);
+   while slot + window + Duration::minutes(MISSED_SLOT_TOLERANCE_MINUTES) < now {
+      missed.push(slot);
+      (slot, window) = get_next_allowed_slot(slot, config, internal_db);
+   }
+
+   if missed.is_empty() {
+      return schedule_slot(slot, window, config, internal_db);
+   }
+
+   // Drop missed slots that are too late to be posted
+   let missed_total = missed.len();
+   if let Some(max_lateness) = config.max_lateness {
+      missed.retain(|missed_slot| *missed_slot + max_lateness >= now);
+   }
+   if missed.len() < missed_total {
+      config.output_message(
+         &format!("Dropped {} missed slots older than max_lateness", missed_total - missed.len()),
+         MessageLevel::Info,
+         MessageOutput::Stdout,
+      );
+   }
+
+   let next_post = schedule_slot(slot, window, config, internal_db);
+   match (config.missed_slots, missed.first(), missed.last()) {
+      (_, None, _) => next_post,
+      (MissedSlotsPolicy::Skip, _, _) => {
+         config.output_message(
+            &format!("Skipped {} missed slots because of missed_slots policy", missed.len()),
+            MessageLevel::Info,
+            MessageOutput::Stdout,
+         );
+         next_post
+      }
+      (MissedSlotsPolicy::One, _, Some(last_missed)) => {
+         // Slot that is due right now is the one post for the missed slots
+         if next_post.time <= config.now() {
+            return next_post;
+         }
+         config.output_message(
+            &format!("Posting once for {} missed slots, last missed at {}", missed.len(), last_missed),
+            MessageLevel::Info,
+            MessageOutput::Stdout,
+         );
+         ScheduledPost { slot: *last_missed, time: now }
+      }
+      (MissedSlotsPolicy::All, Some(first_missed), _) => {
+         config.output_message(
+            &format!("Posting {} missed slots, first missed at {}", missed.len(), first_missed),
+            MessageLevel::Info,
+            MessageOutput::Stdout,
+         );
+         let gap = config.missed_slots_gap.unwrap_or_else(|| Duration::minutes(DEFAULT_MISSED_SLOTS_GAP_MINUTES));
+         let time = last_post.map_or(now, |post| now.max(post.time + gap));
+         ScheduledPost { slot: *first_missed, time }
+      }
+      _ => next_post,
+   }
+}
+
+/// Return next planned post after the given time with random jitter applied
+/// * `date_time` - Time after which the next post should be
+/// * `config` - Configuration of the bot
+/// * `internal_db` - Database where the planned post is stored
+pub fn get_next_post(date_time: DateTime<FixedOffset>, config: &Config, internal_db: &mut ImageDB) -> ScheduledPost {
+   let (slot, window) = get_next_allowed_slot(date_time, config, internal_db);
+   schedule_slot(slot, window, config, internal_db)
+}
+
+/// Apply random jitter to the slot.
+///
+/// Jitter for the same slot is reused from the internal database so restart doesn't roll it again. Jittered time can't fall
+/// into blackout period or quiet hours, the jitter is rolled again or the slot itself is used. Jittered time isn't before now
+/// and keeps the gap of missed slots after the previous post unless the slot itself is closer to it
+/// * `slot` - Slot from the schedule
+/// * `window` - Jitter window of the slot
+/// * `config` - Configuration of the bot
+/// * `internal_db` - Database where the planned post is stored
+fn schedule_slot(slot: DateTime<FixedOffset>, window: Duration, config: &Config, internal_db: &mut ImageDB) -> ScheduledPost {
+   let is_allowed =
+      |time: DateTime<FixedOffset>| get_skip_reason(&to_config_timezone(time, config).naive_local(), config).is_none();
+
+   // Keep already rolled jitter for the same slot if it still fits into the window
+   if let Some(next_post) = &internal_db.next_post {
+      if next_post.slot == slot
+         && (next_post.time - next_post.slot).num_seconds().abs() <= window.num_seconds()
+         && is_allowed(next_post.time)
+      {
+         return next_post.clone();
+      }
+   }
+
+   let now = config.now();
+   let gap = config.missed_slots_gap.unwrap_or_else(|| Duration::minutes(DEFAULT_MISSED_SLOTS_GAP_MINUTES));
+   let earliest = internal_db.last_post.as_ref().map_or(now, |last_post| now.max((last_post.time + gap).min(slot)));
+   let earliest = earliest.with_nanosecond(0).unwrap_or(earliest);
+
+   let time = match window.num_seconds() {
+      0 => slot.max(earliest),
+      window => (0..MAX_JITTER_ROLLS)
+         .map(|_| (slot + Duration::seconds(rand::thread_rng().gen_range(-window..=window))).max(earliest))
+         .find(|time| is_allowed(*time))
+         .unwrap_or(slot.max(earliest)),
+   };
+
+   let next_post = ScheduledPost { slot, time };
+   internal_db.next_post = Some(next_post.clone());
+   next_post
+}
+
+/// Return next slot from the schedule that isn't in blackout period or quiet hours with its jitter window, skipped slots are reported
+/// * `date_time` - Time after which the next slot should be
+/// * `config` - Configuration of the bot
+/// * `internal_db` - Database of the bot for interval schedule
+fn get_next_allowed_slot(
+   date_time: DateTime<FixedOffset>,
+   config: &Config,
+   internal_db: &mut ImageDB,
+) -> (DateTime<FixedOffset>, Duration) {
+   let search_limit = date_time + Duration::days(MAX_SEARCH_DAYS);
+
+   // Skipped slots are reported in groups by the reason to not flood the log
+   let mut skipped: Option<(String, DateTime<FixedOffset>, DateTime<FixedOffset>, usize)> = None;
+   let report_skipped = |skipped: &Option<(String, DateTime<FixedOffset>, DateTime<FixedOffset>, usize)>| {
+      if let Some((reason, first, last, count)) = skipped {
+         let message = match count {
+            1 => format!("Skipped slot at {} because of {}", first, reason),
+            _ => format!("Skipped {} slots between {} and {} because of {}", count, first, last, reason),
+         };
+         config.output_message(&message, MessageLevel::Info, MessageOutput::Stdout);
+      }
+   };
+
+   let mut search_from = date_time;
+   loop {
+      let (slot, slot_jitter) = match config.interval {
+         Some(interval) => (get_next_interval_time(search_from, interval, config, internal_db), None),
+         None => get_next_post_time(search_from, config),
+      };
+
+      if slot > search_limit {
+         config.panic_message(
+            &format!("No post time outside of blackouts and quiet hours found in the next {} days", MAX_SEARCH_DAYS),
+            MessageLevel::Critical,
+         );
+      }
+
+      let Some(reason) = get_skip_reason(&to_config_timezone(slot, config).naive_local(), config) else {
+         report_skipped(&skipped);
+         return (slot, slot_jitter.or(config.jitter).unwrap_or_else(Duration::zero));
+      };
+
+      skipped = match skipped {
+         Some((skipped_reason, first, _, count)) if skipped_reason == reason => Some((reason, first, slot, count + 1)),
+         _ => {
+            report_skipped(&skipped);
+            Some((reason, slot, slot, 1))
+         }
+      };
+      search_from = slot;
+   }
+}
+
+/// Return reason why no post can be made at the given time or None if posting is allowed
+/// * `date_time` - Local time in the timezone of the schedule
+/// * `config` - Configuration of the bot
+fn get_skip_reason(date_time: &NaiveDateTime, config: &Config) -> Option<String> {
+   if let Some(blackout) = config.blackouts.iter().find(|blackout| blackout.contains(date_time)) {
+      return Some(format!("blackout {}", blackout));
+   }
+   if let Some(quiet_hours) = config.quiet_hours.iter().find(|quiet_hours| quiet_hours.contains(&date_time.time())) {
+      return Some(format!("quiet hours {}", quiet_hours));
+   }
+   None
+}
+
+/// Return next time of the interval schedule that is after the given time.
+///
+/// Anchor of the interval is kept in the internal database so restart doesn't shift the cadence
+/// * `date_time` - Time after which the next post should be
+/// * `interval` - Interval between posts
+/// * `config` - Configuration of the bot
+/// * `internal_db` - Database where the anchor is stored
+fn get_next_interval_time(
+   date_time: DateTime<FixedOffset>,
+   interval: Duration,
+   config: &Config,
+   internal_db: &mut ImageDB,
+) -> DateTime<FixedOffset> {
+   let now = config.now();
+   let now = now.with_nanosecond(0).unwrap_or(now);
+
+   // Keep stored anchor unless the configured start of the interval changed
+   let anchor = match (internal_db.interval_anchor, config.interval_start) {
+      (Some(anchor), None) => anchor,
+      (Some(anchor), Some(start)) if to_config_timezone(anchor, config).time() == start => anchor,
+      (_, start) => {
+         let anchor = start.and_then(|start| from_config_local_time(now.date_naive().and_time(start), config)).unwrap_or(now);
+         internal_db.interval_anchor = Some(anchor);
+         anchor
+      }
+   };
+
+   if anchor > date_time {
+      return anchor;
+   }
+
+   let periods = (date_time - anchor).num_seconds() / interval.num_seconds() + 1;
+   anchor + Duration::seconds(interval.num_seconds() * periods)
+}
+
+/// Convert time to the timezone from the config (or local timezone if not set)
+/// * `date_time` - Time to convert
+/// * `config` - Configuration of the bot
+fn to_config_timezone(date_time: DateTime<FixedOffset>, config: &Config) -> DateTime<FixedOffset> {
+   match config.timezone {
+      Some(timezone) => to_fixed_offset(date_time.with_timezone(&timezone)),
+      None => to_fixed_offset(date_time.with_timezone(&Local)),
+   }
+}
+
+/// Interpret local date and time in the timezone from the config (or local timezone if not set)
+/// * `date_time` - Local date and time
+/// * `config` - Configuration of the bot
+fn from_config_local_time(date_time: NaiveDateTime, config: &Config) -> Option<DateTime<FixedOffset>> {
+   match config.timezone {
+      Some(timezone) => timezone.from_local_datetime(&date_time).earliest().map(to_fixed_offset),
+      None => Local.from_local_datetime(&date_time).earliest().map(to_fixed_offset),
+   }
+}
+
+/// Return next closest time that is after the given time given times in config or given time + 1 day if no times are configured.
+/// Jitter window of the time is returned as well if it has its own.
+///
+/// Times are evaluated in the timezone from the config (or local timezone if not set)
+fn get_next_post_time(date_time: DateTime<FixedOffset>, config: &Config) -> (DateTime<FixedOffset>, Option<Duration>) {
+   match config.timezone {
+      Some(timezone) => {
+         let (post_date_time, jitter) = get_next_post_time_in_timezone(date_time.with_timezone(&timezone), config);
+         (to_fixed_offset(post_date_time), jitter)
+      }
+      None => {
+         let (post_date_time, jitter) = get_next_post_time_in_timezone(date_time.with_timezone(&Local), config);
+         (to_fixed_offset(post_date_time), jitter)
+      }
+   }
+}
+
+/// Convert time to the fixed offset of its timezone at that moment
+/// * `date_time` - Time to convert
+fn to_fixed_offset<Tz: TimeZone>(date_time: DateTime<Tz>) -> DateTime<FixedOffset> {
+   let offset = date_time.offset().fix();
+   date_time.with_timezone(&offset)
+}
+
+/// Return next closest time that is after the given time given times in config, evaluated in the timezone of `date_time`
+#[allow(deprecated)]
+fn get_next_post_time_in_timezone<Tz: TimeZone>(date_time: DateTime<Tz>, config: &Config) -> (DateTime<Tz>, Option<Duration>) {
+   if config.times.is_empty() {
+      return (date_time + chrono::Duration::days(1), None);
+   }
+
+   let mut current_date = date_time.date();
+   let search_limit = current_date.clone() + chrono::Duration::days(MAX_SEARCH_DAYS);
+
+   //Loop until time is found
+   loop {
+      if current_date > search_limit {
+         config.panic_message(
+            &format!("No post time found in the next {} days, check times in the configuration", MAX_SEARCH_DAYS),
+            MessageLevel::Critical,
+         );
+      }
+
+      //Try all times in the config for the current day
+      let post_date_times = get_post_times_for_date(&current_date, &date_time, config);
+      if let Some(post_date_time) = post_date_times.into_iter().find(|(post_date_time, _)| &date_time < post_date_time) {
+         return post_date_time;
+      }
+
+      //Add one day if no time in config is in the future for current day
+      current_date += chrono::Duration::days(1);
+   }
+}
+
+/// Return sorted list of all post times (with their own jitter window) from the config that happen on the given date, based on the time table
+/// * `current_date` - Date for which to get the post times
+/// * `date_time` - Time from which the next post time is searched, used only for reporting skipped times
+/// * `config` - Configuration of the bot
+#[allow(deprecated)]
+fn get_post_times_for_date<Tz: TimeZone>(
+   current_date: &chrono::Date<Tz>,
+   date_time: &DateTime<Tz>,
+   config: &Config,
+) -> Vec<(DateTime<Tz>, Option<Duration>)> {
+   let mut post_date_times = Vec::new();
+
+   for post_time in config.times.get_times_for_date(&current_date.naive_local()) {
+      match &post_time.at {
+         PostTimeKind::Clock(hours, minutes) => {
+            match current_date.and_hms_opt(hours.to_owned() as u32, minutes.to_owned() as u32, 0) {
+               Some(new_date_time) => post_date_times.push((new_date_time, post_time.jitter)),
+               None => {
+                  if *hours <= 23 && *minutes <= 59 {
+                     // Check if we need to show the information about skipped time or if it's no longer relevant
+                     let Some(time) = NaiveTime::from_hms_opt(*hours as u32, *minutes as u32, 0) else {
+                        // Could be panic here, but no reason to crash service just when trying to see if to print message (and this shouldn't happen anyway)
+                        config.output_message(
+                           &format!("Can't make time {}:{} while checking Daylight Saving Time", hours, minutes),
+                           MessageLevel::Warning,
+                           MessageOutput::Stdout,
+                        );
+                        continue;
+                     };
+                     if *current_date > date_time.date() || (*current_date == date_time.date() && time > date_time.time()) {
+                        config.output_message(
+                           &format!("Skipped time {}:{} because it doesn't exist due to Daylight Saving Time", hours, minutes),
+                           MessageLevel::Info,
+                           MessageOutput::Stdout,
+                        );
+                     }
+                     continue; //Hours and minutes are correct, but probably daylight saving time make the specific time not exist
+                  }
+                  config.panic_message(
+                     &format!("Invalid hours or minutes in the configuration: hours: {}, minutes: {}", hours, minutes),
+                     MessageLevel::Critical,
+                  );
+               }
+            };
+         }
+         PostTimeKind::Cron(schedule) => {
+            // Start searching just before the midnight so the cron can fire at 00:00 as well
+            let Some(day_start) = current_date.and_hms_opt(0, 0, 0).or_else(|| current_date.and_hms_opt(1, 0, 0)) else {
+               continue;
+            };
+            let search_start = day_start - chrono::Duration::seconds(1);
+            post_date_times.extend(
+               schedule
+                  .after(&search_start)
+                  .take_while(|post_date_time| post_date_time.date() == *current_date)
+                  .map(|post_date_time| (post_date_time, post_time.jitter)),
+            );
+         }
+      }
+   }
+
+   post_date_times.sort_by(|(first, _), (second, _)| first.cmp(second));
+   post_date_times
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::structures::test_config;
+   use crate::structures::RetryState;
+
+   fn post(time: DateTime<FixedOffset>) -> ScheduledPost {
+      ScheduledPost { slot: time, time }
+   }
+
+   /// Database with interval anchor 5h30m ago and last post 4h30m ago, slots 3h30m to 30m ago were missed
+   fn database(config: &Config) -> (ImageDB, DateTime<FixedOffset>) {
+      let now = config.now().with_nanosecond(0).expect("valid time");
+      let anchor = now - Duration::minutes(330);
+      let internal_db =
+         ImageDB { interval_anchor: Some(anchor), last_post: Some(post(anchor + Duration::hours(1))), ..Default::default() };
+      (internal_db, anchor)
+   }
+
+   fn assert_now(config: &Config, time: DateTime<FixedOffset>) {
+      assert!((config.now() - time).num_seconds().abs() <= 2, "{} should be now", time);
+   }
+
+   #[test]
+   fn skip_policy_plans_next_slot() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "skip"}));
+      let (mut internal_db, anchor) = database(&config);
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(6));
+      assert_eq!(next_post.time, next_post.slot);
+   }
+
+   #[test]
+   fn one_policy_posts_last_missed_slot_now() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "one"}));
+      let (mut internal_db, anchor) = database(&config);
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(5));
+      assert_now(&config, next_post.time);
+   }
+
+   #[test]
+   fn all_policy_posts_first_missed_slot_now() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "all"}));
+      let (mut internal_db, anchor) = database(&config);
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(2));
+      assert_now(&config, next_post.time);
+
+      // Following missed slot keeps the gap after the previous post
+      internal_db.last_post = Some(next_post.clone());
+      let following = plan_next_post(&config, &mut internal_db);
+      assert_eq!(following.slot, anchor + Duration::hours(3));
+      assert_eq!(following.time, next_post.time + Duration::minutes(DEFAULT_MISSED_SLOTS_GAP_MINUTES));
+   }
+
+   #[test]
+   fn all_policy_drops_slots_older_than_max_lateness() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "all", "max_lateness": "2h"}));
+      let (mut internal_db, anchor) = database(&config);
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(4));
+   }
+
+   #[test]
+   fn retried_slot_is_not_missed() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "one"}));
+      let (mut internal_db, anchor) = database(&config);
+      internal_db.last_post = Some(post(anchor + Duration::hours(4)));
+      internal_db.retry =
+         Some(RetryState { slot: anchor + Duration::hours(5), attempts: 1, next_attempt: config.now() + Duration::hours(1) });
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(6));
+   }
+
+   #[test]
+   fn abandoned_slot_is_not_missed() {
+      let config = test_config(serde_json::json!({"interval": "1h", "missed_slots": "all"}));
+      let (mut internal_db, anchor) = database(&config);
+      internal_db.abandoned_slot = Some(anchor + Duration::hours(5));
+      let next_post = plan_next_post(&config, &mut internal_db);
+      assert_eq!(next_post.slot, anchor + Duration::hours(6));
+   }
+
+   #[test]
+   fn jittered_time_avoids_quiet_hours() {
+      let config = test_config(serde_json::json!({"interval": "1h", "quiet_hours": ["22:00-07:00"]}));
+      let tomorrow = (config.now() + Duration::days(1)).date_naive();
+      let slot = tomorrow.and_hms_opt(21, 55, 0).expect("valid time").and_utc().fixed_offset();
+      for _ in 0..50 {
+         let mut internal_db = ImageDB::default();
+         let next_post = schedule_slot(slot, Duration::minutes(30), &config, &mut internal_db);
+         assert!(next_post.time < slot + Duration::minutes(5), "{} is in quiet hours", next_post.time);
+         assert!(next_post.time >= slot - Duration::minutes(30));
+      }
+   }
+
+   #[test]
+   fn jittered_time_is_not_before_now_or_too_close_to_last_post() {
+      let config = test_config(serde_json::json!({"interval": "1h"}));
+      let now = config.now().with_nanosecond(0).expect("valid time");
+      for _ in 0..50 {
+         let mut internal_db = ImageDB::default();
+         let next_post = schedule_slot(now + Duration::minutes(5), Duration::minutes(30), &config, &mut internal_db);
+         assert!(next_post.time >= now, "{} is before now", next_post.time);
+
+         let last_post = ScheduledPost { slot: now + Duration::hours(1), time: now + Duration::minutes(85) };
+         let mut internal_db = ImageDB { last_post: Some(last_post.clone()), ..Default::default() };
+         let next_post = schedule_slot(now + Duration::hours(2), Duration::minutes(50), &config, &mut internal_db);
+         assert!(next_post.time >= last_post.time + Duration::minutes(DEFAULT_MISSED_SLOTS_GAP_MINUTES));
+      }
+   }
+
+   #[test]
+   fn jitter_has_to_be_smaller_than_half_of_gap() {
+      assert!(check_jitter(&test_config(serde_json::json!({"interval": "1h", "jitter": "±29m"}))).is_ok());
+      assert!(check_jitter(&test_config(serde_json::json!({"interval": "1h", "jitter": "±30m"}))).is_err());
+      assert!(check_jitter(&test_config(serde_json::json!({"times": ["10:00", "10:30", "18:00"], "jitter": "±14m"}))).is_ok());
+      assert!(check_jitter(&test_config(serde_json::json!({"times": ["10:00", "10:30", "18:00"], "jitter": "±15m"}))).is_err());
+      let times = serde_json::json!([{"time": "10:00", "jitter": "±20m"}, "10:30", "18:00"]);
+      assert!(check_jitter(&test_config(serde_json::json!({"times": times, "jitter": "±5m"}))).is_ok());
+      assert!(check_jitter(&test_config(serde_json::json!({"times": times, "jitter": "±10m"}))).is_err());
+   }
+}
@@ -1,11 +1,16 @@
 use std::{
+   collections::HashMap,
    fmt::{Display, Formatter},
    fs::File,
    io::Write,
    path::{Path, PathBuf},
    process::exit,
+   str::FromStr,
 };
 
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc, Weekday};
+use chrono_tz::Tz;
+use cron::Schedule;
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
 pub enum GetImageErrorLevel {
@@ -13,6 +18,23 @@ pub enum GetImageErrorLevel {
    Critical(anyhow::Error),
 }
 
+/// Error of posting image, decides if the same image is posted on the next attempt
+pub enum PostErrorLevel {
+   /// Posting can succeed on the next attempt, e.g. the server is unreachable
+   Retry(anyhow::Error),
+   /// Image can't be posted and it is skipped
+   ImageUnusable(anyhow::Error),
+}
+
+impl From<GetImageErrorLevel> for PostErrorLevel {
+   fn from(error: GetImageErrorLevel) -> Self {
+      match error {
+         GetImageErrorLevel::Normal(error) => PostErrorLevel::Retry(error),
+         GetImageErrorLevel::Critical(error) => PostErrorLevel::ImageUnusable(error),
+      }
+   }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialOrd, PartialEq, Ord, Eq)]
 pub enum MessageLevel {
    Emergency = 0,
@@ -52,6 +74,33 @@ impl Display for StatusVisibility {
    }
 }
 
+/// Policy for slots missed while the bot wasn't running
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedSlotsPolicy {
+   /// Don't post missed slots at all
+   Skip,
+   /// Post once for all missed slots
+   One,
+   /// Post every missed slot separated by minimal gap
+   All,
+}
+
+/// Order in which images are picked
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+   /// Pick images at random
+   Random,
+   /// Pick images in order of the image json file
+   Sequential,
+   /// Pick images in reversed order of the image json file
+   Reverse,
+   /// Pick new images at random and then images that weren't posted for the longest time
+   #[serde(rename = "least_recent")]
+   LeastRecent,
+}
+
 ///Structure holding configuration of the bot
 #[derive(Deserialize, Debug)]
 pub struct Config {
@@ -62,8 +111,8 @@ pub struct Config {
    image_json: String,
    #[serde(alias = "not_used_images_log_location")]
    internal_database: String,
-   #[serde(deserialize_with = "from_string_time")]
-   pub times: Vec<(u8, u8)>,
+   #[serde(default, deserialize_with = "from_time_table")]
+   pub times: TimeTable,
    #[serde(default)]
    pub tags: String,
    local_path: Option<String>,
@@ -72,10 +121,67 @@ pub struct Config {
    pub log_level: MessageLevel,
    #[serde(default = "default_retry_time")]
    pub retry_time: u64,
+   /// Multiplier of the retry delay after every failed attempt
+   #[serde(default = "default_retry_multiplier")]
+   pub retry_multiplier: f64,
+   /// Maximal delay between retries
+   #[serde(default, deserialize_with = "from_duration")]
+   pub retry_max_delay: Option<Duration>,
+   /// Maximal number of attempts to post for one slot, unlimited if not set
+   #[serde(default)]
+   pub retry_max_attempts: Option<u32>,
+   /// Number of failed attempts with the same image after which different image is picked
+   #[serde(default)]
+   pub retry_change_image_after: Option<u32>,
    #[serde(default = "default_status_visibility")]
    pub status_visibility: StatusVisibility,
    #[serde(default, deserialize_with = "from_status_visibility_sequence")]
    pub status_visibility_sequence: Option<Vec<StatusVisibility>>,
+   /// IANA name of the timezone for the schedule, local timezone of the system is used if not set
+   #[serde(default)]
+   pub timezone: Option<Tz>,
+   /// Random offset window around every post time, e.g. "±20m"
+   #[serde(default, deserialize_with = "from_jitter")]
+   pub jitter: Option<Duration>,
+   /// Post in regular interval instead of fixed times, e.g. "3h" or "1h30m"
+   #[serde(default, deserialize_with = "from_duration")]
+   pub interval: Option<Duration>,
+   /// Time of the day from which the interval is counted, time of the first start is used if not set
+   #[serde(default, deserialize_with = "from_clock_time")]
+   pub interval_start: Option<NaiveTime>,
+   /// Periods of dates during which no post is made
+   #[serde(default, deserialize_with = "from_blackouts")]
+   pub blackouts: Vec<Blackout>,
+   /// Daily windows during which no post is made
+   #[serde(default, deserialize_with = "from_quiet_hours")]
+   pub quiet_hours: Vec<QuietHours>,
+   /// What to do with slots missed while the bot wasn't running
+   #[serde(default = "default_missed_slots")]
+   pub missed_slots: MissedSlotsPolicy,
+   /// Missed slots older than this are not posted anymore
+   #[serde(default, deserialize_with = "from_duration")]
+   pub max_lateness: Option<Duration>,
+   /// Minimal gap between posts of missed slots with the "all" policy
+   #[serde(default, deserialize_with = "from_duration")]
+   pub missed_slots_gap: Option<Duration>,
+   /// Order in which images are picked
+   #[serde(default = "default_selection")]
+   pub selection: Selection,
+   /// Number of days during which the same image isn't posted again
+   #[serde(default)]
+   pub repost_cooldown: Option<u32>,
+   /// Order of categories of posted images, e.g. ["fox", "fox", "landscape"]
+   #[serde(default, deserialize_with = "from_category_rotation")]
+   pub category_rotation: Option<Vec<String>>,
+   /// Number of last posts whose albums aren't posted again
+   #[serde(default)]
+   pub min_album_gap: Option<usize>,
+   /// Seed of random number generator for picking images, images are picked the same way across restarts if set
+   #[serde(default)]
+   pub rng_seed: Option<u64>,
+   /// Default language of posts as ISO 639-1 code
+   #[serde(default, deserialize_with = "from_language")]
+   pub language: Option<String>,
 }
 
 fn default_log_level() -> MessageLevel {
@@ -86,10 +192,22 @@ fn default_retry_time() -> u64 {
    10 * 60 // 10 minutes
 }
 
+fn default_retry_multiplier() -> f64 {
+   2.0
+}
+
 fn default_status_visibility() -> StatusVisibility {
    StatusVisibility::Default
 }
 
+fn default_missed_slots() -> MissedSlotsPolicy {
+   MissedSlotsPolicy::One
+}
+
+fn default_selection() -> Selection {
+   Selection::Random
+}
+
 impl Config {
    /// Function to print message with correct level, output and systemd prefix if needed
    /// * `message` - Message to be printed
@@ -132,6 +250,17 @@ impl Config {
       exit(1);
    }
 
+   /// Get current time in the timezone of the schedule
+   pub fn now(&self) -> DateTime<FixedOffset> {
+      match self.timezone {
+         Some(timezone) => {
+            let now = Utc::now().with_timezone(&timezone);
+            now.with_timezone(&now.offset().fix())
+         }
+         None => Local::now().into(),
+      }
+   }
+
    /// Get path to the database file relative from the config file
    pub fn get_internal_database_path(&self) -> PathBuf {
       let path = Path::new(&self.internal_database);
@@ -173,40 +302,460 @@ impl Config {
    }
 }
 
-fn from_string_time<'de, D>(deserializer: D) -> Result<Vec<(u8, u8)>, D::Error>
+/// Single post time from the configuration
+#[derive(Debug, Clone)]
+pub struct PostTime {
+   /// When the post should happen
+   pub at: PostTimeKind,
+   /// Random offset window specific for this time, overrides global jitter
+   pub jitter: Option<Duration>,
+}
+
+/// Kind of the post time from the configuration
+#[derive(Debug, Clone)]
+pub enum PostTimeKind {
+   /// Fixed time of the day as hours and minutes
+   Clock(u8, u8),
+   /// Cron expression for more complex schedules
+   Cron(Box<Schedule>),
+}
+
+/// Post times of the bot, either same for every day or defined per weekday and specific dates
+#[derive(Debug, Default)]
+pub struct TimeTable {
+   /// Times used for days without their own entry
+   pub default: Vec<PostTime>,
+   /// Times for specific weekdays
+   pub weekdays: HashMap<Weekday, Vec<PostTime>>,
+   /// Times for specific dates, overriding weekday and default times
+   pub dates: HashMap<NaiveDate, Vec<PostTime>>,
+}
+
+impl TimeTable {
+   /// Get post times for the given date, date entries take precedence over weekday entries and default times
+   /// * `date` - Date to get the times for
+   pub fn get_times_for_date(&self, date: &NaiveDate) -> &[PostTime] {
+      if let Some(times) = self.dates.get(date) {
+         return times;
+      }
+      match self.weekdays.get(&date.weekday()) {
+         Some(times) => times,
+         None => &self.default,
+      }
+   }
+
+   /// Check if there is no recurring post time, specific dates alone can't make schedule
+   pub fn is_empty(&self) -> bool {
+      self.default.is_empty() && self.weekdays.values().all(|times| times.is_empty())
+   }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TimeEntry {
+   Simple(String),
+   WithJitter { time: String, jitter: String },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TimeTableItem {
+   List(Vec<TimeEntry>),
+   Table(HashMap<String, Vec<TimeEntry>>),
+}
+
+fn from_time_table<'de, D>(deserializer: D) -> Result<TimeTable, D::Error>
 where
    D: Deserializer<'de>,
 {
-   let s: Vec<String> = Deserialize::deserialize(deserializer)?;
+   let deserialized: Result<TimeTableItem, _> = Deserialize::deserialize(deserializer);
 
-   // Deserializing time to tuple with hours and minutes
-   s.into_iter()
-      .map(|time| -> Result<(u8, u8), <D as Deserializer>::Error> {
-         let mut time_split = time.split(':');
-         let hours = time_split
-            .next()
-            .ok_or_else(|| D::Error::custom("missing hours"))
-            .and_then(|h| h.parse::<u8>().map_err(|_| D::Error::custom("can't parse hours")));
-         let minutes = time_split
-            .next()
-            .ok_or_else(|| D::Error::custom("missing minutes"))
-            .and_then(|h| h.parse::<u8>().map_err(|_| D::Error::custom("can't parse minutes")));
-         match (hours, minutes) {
-            (Ok(hours), Ok(minutes)) => {
-               if hours > 23 {
-                  Err(D::Error::custom("hours must be less than 23"))
-               } else if minutes > 60 {
-                  Err(D::Error::custom("minutes must be less than 60"))
-               } else {
-                  Ok((hours, minutes))
-               }
+   let parse_times = |times: Vec<TimeEntry>| -> Result<Vec<PostTime>, D::Error> {
+      times
+         .into_iter()
+         .map(|entry| match entry {
+            TimeEntry::Simple(time) => Ok(PostTime { at: parse_post_time(&time).map_err(D::Error::custom)?, jitter: None }),
+            TimeEntry::WithJitter { time, jitter } => Ok(PostTime {
+               at: parse_post_time(&time).map_err(D::Error::custom)?,
+               jitter: Some(parse_jitter(&jitter).map_err(D::Error::custom)?),
+            }),
+         })
+         .collect()
+   };
+
+   match deserialized {
+      Ok(TimeTableItem::List(times)) => Ok(TimeTable { default: parse_times(times)?, ..Default::default() }),
+      Ok(TimeTableItem::Table(table)) => {
+         let mut time_table = TimeTable::default();
+         for (key, times) in table {
+            let times = parse_times(times)?;
+            if key.eq_ignore_ascii_case("default") {
+               time_table.default = times;
+            } else if let Ok(weekday) = Weekday::from_str(&key) {
+               time_table.weekdays.insert(weekday, times);
+            } else if let Ok(date) = NaiveDate::parse_from_str(&key, "%Y-%m-%d") {
+               time_table.dates.insert(date, times);
+            } else {
+               return Err(D::Error::custom(format!(
+                  "Unknown key '{}' in times, allowed is \"default\", name of weekday or date in format YYYY-MM-DD",
+                  key
+               )));
             }
-            (Err(hours), Ok(_)) => Err(hours),
-            (Ok(_), Err(minutes)) => Err(minutes),
-            _ => Err(D::Error::custom("invalid time")),
          }
+         Ok(time_table)
+      }
+      Err(_) => Err(D::Error::custom(
+         "Wrong format of times. \
+              Allowed simple array of times or table with arrays of times for \"default\", weekdays and dates, e.g: \
+              [\"8:00\", \"16:00\"] or {\"default\": [\"8:00\"], \"saturday\": [\"10:00\", \"18:00\"], \"2024-12-24\": []}. \
+              Every time can be as well table with time and its own jitter, e.g: {\"time\": \"8:00\", \"jitter\": \"±15m\"}",
+      )),
+   }
+}
+
+/// Period during which no post is made, times are local in the timezone of the schedule
+#[derive(Debug, Clone)]
+pub struct Blackout {
+   /// Start of the period (inclusive)
+   pub start: NaiveDateTime,
+   /// End of the period (exclusive)
+   pub end: NaiveDateTime,
+   /// Optional reason for the blackout shown in the log
+   pub reason: Option<String>,
+}
+
+impl Blackout {
+   /// Check if the time is inside of the blackout
+   /// * `date_time` - Local time to check
+   pub fn contains(&self, date_time: &NaiveDateTime) -> bool {
+      &self.start <= date_time && date_time < &self.end
+   }
+}
+
+impl Display for Blackout {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      match &self.reason {
+         Some(reason) => write!(f, "'{}'", reason),
+         None => write!(f, "{} - {}", self.start.format("%Y-%m-%d %H:%M"), self.end.format("%Y-%m-%d %H:%M")),
+      }
+   }
+}
+
+#[derive(Deserialize)]
+struct BlackoutItem {
+   start: String,
+   end: Option<String>,
+   reason: Option<String>,
+}
+
+fn from_blackouts<'de, D>(deserializer: D) -> Result<Vec<Blackout>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let items: Vec<BlackoutItem> = Deserialize::deserialize(deserializer)?;
+
+   items
+      .into_iter()
+      .map(|item| {
+         let start = parse_blackout_bound(&item.start, false).map_err(D::Error::custom)?;
+         let end = parse_blackout_bound(item.end.as_deref().unwrap_or(&item.start), true).map_err(D::Error::custom)?;
+         if end <= start {
+            return Err(D::Error::custom(format!("blackout starting at {} has to end after it starts", item.start)));
+         }
+         Ok(Blackout { start, end, reason: item.reason })
       })
-      .collect::<Result<Vec<(u8, u8)>, D::Error>>()
+      .collect()
+}
+
+/// Parse start or end of the blackout either as "YYYY-MM-DD" or "YYYY-MM-DD HH:MM"
+/// * `bound` - Date or date with time from the configuration
+/// * `is_end` - End given only as date includes whole day
+fn parse_blackout_bound(bound: &str, is_end: bool) -> Result<NaiveDateTime, String> {
+   let bound = bound.trim();
+   if let Ok(date_time) = NaiveDateTime::parse_from_str(bound, "%Y-%m-%d %H:%M") {
+      return Ok(date_time);
+   }
+
+   match NaiveDate::parse_from_str(bound, "%Y-%m-%d") {
+      Ok(date) if is_end => Ok(date.and_time(NaiveTime::MIN) + Duration::days(1)),
+      Ok(date) => Ok(date.and_time(NaiveTime::MIN)),
+      Err(_) => Err(format!("invalid blackout date '{}', expected YYYY-MM-DD or YYYY-MM-DD HH:MM", bound)),
+   }
+}
+
+/// Daily window during which no post is made, can go over midnight
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+   /// Start of the window (inclusive)
+   pub start: NaiveTime,
+   /// End of the window (exclusive)
+   pub end: NaiveTime,
+}
+
+impl QuietHours {
+   /// Check if the time of the day is inside of the quiet hours
+   /// * `time` - Local time to check
+   pub fn contains(&self, time: &NaiveTime) -> bool {
+      if self.start <= self.end {
+         &self.start <= time && time < &self.end
+      } else {
+         &self.start <= time || time < &self.end
+      }
+   }
+}
+
+impl Display for QuietHours {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+   }
+}
+
+fn from_quiet_hours<'de, D>(deserializer: D) -> Result<Vec<QuietHours>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let windows: Vec<String> = Deserialize::deserialize(deserializer)?;
+
+   let parse_time = |time: &str| match parse_post_time(time) {
+      Ok(PostTimeKind::Clock(hours, minutes)) => NaiveTime::from_hms_opt(hours as u32, minutes as u32, 0),
+      _ => None,
+   };
+
+   windows
+      .iter()
+      .map(|window| {
+         let bounds = window.split_once('-').and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)));
+         match bounds {
+            Some((start, end)) => Ok(QuietHours { start, end }),
+            None => Err(D::Error::custom(format!("invalid quiet hours '{}', expected format HH:MM-HH:MM", window))),
+         }
+      })
+      .collect()
+}
+
+/// Parse post time either as "HH:MM" or as cron expression
+/// * `time` - Time string from the configuration
+fn parse_post_time(time: &str) -> Result<PostTimeKind, String> {
+   let time = time.trim();
+
+   // Anything with whitespace is cron expression, "HH:MM" never contains it
+   if time.contains(char::is_whitespace) {
+      return parse_cron_time(time);
+   }
+
+   // Deserializing time to tuple with hours and minutes
+   let mut time_split = time.split(':');
+   let hours = time_split
+      .next()
+      .ok_or_else(|| "missing hours".to_string())
+      .and_then(|h| h.parse::<u8>().map_err(|_| "can't parse hours".to_string()));
+   let minutes = time_split
+      .next()
+      .ok_or_else(|| "missing minutes".to_string())
+      .and_then(|h| h.parse::<u8>().map_err(|_| "can't parse minutes".to_string()));
+   match (hours, minutes) {
+      (Ok(hours), Ok(minutes)) => {
+         if hours > 23 {
+            Err("hours must be less than 23".to_string())
+         } else if minutes > 60 {
+            Err("minutes must be less than 60".to_string())
+         } else {
+            Ok(PostTimeKind::Clock(hours, minutes))
+         }
+      }
+      (Err(hours), Ok(_)) => Err(hours),
+      (Ok(_), Err(minutes)) => Err(minutes),
+      _ => Err("invalid time".to_string()),
+   }
+}
+
+/// Parse cron expression, standard 5 field expressions (without seconds) are allowed as well.
+///
+/// Numeric days of week of 5 field expressions are standard (0 or 7 is Sunday), full expressions use 1 for Sunday
+/// * `expression` - Cron expression from the configuration
+fn parse_cron_time(expression: &str) -> Result<PostTimeKind, String> {
+   let fields: Vec<&str> = expression.split_whitespace().collect();
+   let full_expression = match fields.as_slice() {
+      [minutes, hours, days, months, days_of_week] => {
+         let days_of_week = match convert_days_of_week(days_of_week) {
+            Ok(days_of_week) => days_of_week,
+            Err(e) => return Err(format!("invalid cron expression '{}': {}", expression, e)),
+         };
+         format!("0 {} {} {} {} {}", minutes, hours, days, months, days_of_week)
+      }
+      _ => expression.to_string(),
+   };
+
+   let schedule = match Schedule::from_str(&full_expression) {
+      Ok(schedule) => schedule,
+      Err(e) => return Err(format!("invalid cron expression '{}': {}", expression, e)),
+   };
+
+   if schedule.upcoming(Utc).next().is_none() {
+      return Err(format!("cron expression '{}' never fires", expression));
+   }
+
+   Ok(PostTimeKind::Cron(Box::new(schedule)))
+}
+
+/// Convert numeric days of week from standard cron (0 or 7 is Sunday) to the numbering of cron crate (1 is Sunday).
+///
+/// Numeric ranges and steps are expanded to list of days as ranges over Sunday can't be converted, names in ranges with
+/// numbers are converted as well and items with names only are kept
+/// * `days_of_week` - Day of week field of standard cron expression
+fn convert_days_of_week(days_of_week: &str) -> Result<String, String> {
+   const DAY_NAMES: [(&str, &str); 7] = [
+      ("sun", "sunday"),
+      ("mon", "monday"),
+      ("tue", "tuesday"),
+      ("wed", "wednesday"),
+      ("thu", "thursday"),
+      ("fri", "friday"),
+      ("sat", "saturday"),
+   ];
+
+   let mut converted = Vec::new();
+   for item in days_of_week.split(',') {
+      let (range, step) = match item.split_once('/') {
+         Some((range, step)) => match step.parse::<usize>() {
+            Ok(step) if step > 0 => (range, Some(step)),
+            _ => return Err(format!("invalid step '{}' of day of week", step)),
+         },
+         None => (item, None),
+      };
+      if item == "*" || range == "?" || (range != "*" && !range.chars().any(|c| c.is_ascii_digit())) {
+         converted.push(item.to_string());
+         continue;
+      }
+
+      let parse_day = |day: &str| {
+         let name = day.to_ascii_lowercase();
+         match (day.parse::<usize>(), DAY_NAMES.iter().position(|(short, long)| name == *short || name == *long)) {
+            (Ok(day), _) if day <= 7 => Ok(day),
+            (_, Some(day)) => Ok(day),
+            _ => Err(format!("invalid day of week '{}', expected 0-7 or name of the day", day)),
+         }
+      };
+      let (start, end) = match (range, range.split_once('-')) {
+         ("*", _) => (0, 6),
+         (_, Some((start, end))) => (parse_day(start)?, parse_day(end)?),
+         // Start with step continues till the end of week
+         (day, None) => (parse_day(day)?, if step.is_some() { 6 } else { parse_day(day)? }),
+      };
+      if start > end {
+         return Err(format!("invalid range '{}' of day of week", range));
+      }
+      let days = (start..=end).step_by(step.unwrap_or(1)).map(|day| (day % 7 + 1).to_string());
+      converted.extend(days);
+   }
+   Ok(converted.join(","))
+}
+
+/// Maximal length of duration in the configuration
+const MAX_DURATION_DAYS: i64 = 366;
+
+/// Maximal jitter window, posts from neighbouring days would swap with longer one
+const MAX_JITTER_HOURS: i64 = 12;
+
+/// Parse duration in format like "90s", "20m", "1h30m" or "2d"
+/// * `duration` - Duration string from the configuration
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+   let duration_trimmed = duration.trim();
+   if duration_trimmed.is_empty() {
+      return Err("duration can't be empty".to_string());
+   }
+
+   let mut total = Duration::zero();
+   let mut number = String::new();
+   for character in duration_trimmed.chars() {
+      if character.is_ascii_digit() {
+         number.push(character);
+         continue;
+      }
+      if character.is_whitespace() {
+         continue;
+      }
+
+      let value = match number.parse::<i64>() {
+         Ok(value) => value,
+         Err(_) if number.is_empty() => return Err(format!("missing number before '{}' in duration '{}'", character, duration)),
+         Err(_) => return Err(format!("duration '{}' can't be longer than {} days", duration, MAX_DURATION_DAYS)),
+      };
+      number.clear();
+
+      let part = match character {
+         'd' => Duration::try_days(value),
+         'h' => Duration::try_hours(value),
+         'm' => Duration::try_minutes(value),
+         's' => Duration::try_seconds(value),
+         _ => return Err(format!("unknown unit '{}' in duration '{}', allowed are d, h, m and s", character, duration)),
+      };
+      total = match part.and_then(|part| total.checked_add(&part)) {
+         Some(total) if total <= Duration::days(MAX_DURATION_DAYS) => total,
+         _ => return Err(format!("duration '{}' can't be longer than {} days", duration, MAX_DURATION_DAYS)),
+      };
+   }
+
+   if !number.is_empty() {
+      return Err(format!("missing unit after number in duration '{}'", duration));
+   }
+
+   Ok(total)
+}
+
+/// Parse jitter window in format like "±20m", "+-20m" or "20m"
+/// * `jitter` - Jitter string from the configuration
+fn parse_jitter(jitter: &str) -> Result<Duration, String> {
+   let window = jitter.trim();
+   let window = window.strip_prefix('±').or_else(|| window.strip_prefix("+-")).unwrap_or(window);
+   match parse_duration(window)? {
+      window if window > Duration::hours(MAX_JITTER_HOURS) => {
+         Err(format!("jitter '{}' can't be longer than {} hours", jitter, MAX_JITTER_HOURS))
+      }
+      window => Ok(window),
+   }
+}
+
+fn from_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let duration: Option<String> = Deserialize::deserialize(deserializer)?;
+   match duration {
+      Some(duration) => match parse_duration(&duration) {
+         Ok(duration) if duration > Duration::zero() => Ok(Some(duration)),
+         Ok(_) => Err(D::Error::custom(format!("duration '{}' has to be longer than zero", duration))),
+         Err(e) => Err(D::Error::custom(e)),
+      },
+      None => Ok(None),
+   }
+}
+
+fn from_clock_time<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let time: Option<String> = Deserialize::deserialize(deserializer)?;
+   match time {
+      Some(time) => match parse_post_time(&time).map_err(D::Error::custom)? {
+         PostTimeKind::Clock(hours, minutes) => NaiveTime::from_hms_opt(hours as u32, minutes as u32, 0)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid time '{}'", time))),
+         PostTimeKind::Cron(_) => Err(D::Error::custom(format!("expected time in format HH:MM, got '{}'", time))),
+      },
+      None => Ok(None),
+   }
+}
+
+fn from_jitter<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let jitter: Option<String> = Deserialize::deserialize(deserializer)?;
+   match jitter {
+      Some(jitter) => parse_jitter(&jitter).map(Some).map_err(D::Error::custom),
+      None => Ok(None),
+   }
 }
 
 #[derive(Deserialize)]
@@ -245,27 +794,427 @@ where
    }
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CategoryRotationItem {
+   Simple(String),
+   Pair(String, usize),
+}
+
+fn from_category_rotation<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   let deserialized: Result<Vec<CategoryRotationItem>, _> = Deserialize::deserialize(deserializer);
+
+   match deserialized {
+      Ok(rotation) => {
+         let mut simple_rotation = Vec::new();
+         for item in rotation {
+            match item {
+               CategoryRotationItem::Simple(category) => simple_rotation.push(category),
+               CategoryRotationItem::Pair(category, amount) => {
+                  for _ in 0..amount {
+                     simple_rotation.push(category.clone());
+                  }
+               }
+            }
+         }
+         if simple_rotation.is_empty() {
+            return Err(D::Error::custom("category_rotation has to contain at least one category"));
+         }
+         Ok(Some(simple_rotation))
+      }
+      Err(_) => Err(D::Error::custom(
+         "Wrong format of category_rotation. \
+              Allowed simple array or array with tuples of category and amount, e.g: \
+              [\"fox\", \"fox\", \"landscape\"] or [[\"fox\", 2], [\"landscape\",1]]",
+      )),
+   }
+}
+
 ///Structure containing info about the image
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Image {
    /// Optional message
    pub msg: Option<String>,
-   /// Optional alt text for image
-   pub alt: Option<String>,
+   /// Optional alt text for image, list of alt texts in order of locations for multiple images
+   pub alt: Option<OneOrMany>,
    /// Optional content warning
    pub content_warning: Option<String>,
-   /// Link to hosted image
-   pub location: String,
+   /// Optional focal point "x,y" with values from -1.0 to 1.0, list of focal points in order of locations for multiple images
+   #[serde(default)]
+   pub focus: Option<FocusPoints>,
+   /// Link to hosted image or list of up to four links to images posted together
+   pub location: Locations,
+   /// Optional weight for picking the image, default is 1.0
+   #[serde(default, deserialize_with = "from_weight")]
+   pub weight: Option<f64>,
+   /// Optional category used for rotation of categories
+   pub category: Option<String>,
+   /// Optional album of the image, parent directory of the location is used if not set
+   pub album: Option<String>,
+   /// Optional name of series, images of the same series are posted together as reply chain in order of image json
+   pub series: Option<String>,
+   /// Optional first date when the image can be posted, "YYYY-MM-DD" or "MM-DD" for every year
+   #[serde(default)]
+   pub not_before: Option<ImageDate>,
+   /// Optional last date when the image can be posted, "YYYY-MM-DD" or "MM-DD" for every year
+   #[serde(default)]
+   pub not_after: Option<ImageDate>,
+   /// Optional date when the image is posted at the first post time of the day, "YYYY-MM-DD" or "MM-DD" for every year
+   #[serde(default)]
+   pub post_on: Option<ImageDate>,
+   /// Optional language of the post as ISO 639-1 code, default language from configuration is used if not set
+   #[serde(default, deserialize_with = "from_language")]
+   pub language: Option<String>,
+   /// Position of the image in image json file
+   #[serde(skip)]
+   pub position: usize,
 }
 
 impl Image {
    pub fn get_hash(&self) -> String {
-      format!("{:x}", md5::compute(&self.location))
+      // Hash of single image is the hash of its location so the internal database stays valid
+      format!("{:x}", md5::compute(self.location.0.join("\n")))
+   }
+
+   /// Get alt text for image at the index of location
+   pub fn get_alt(&self, index: usize) -> Option<&String> {
+      match &self.alt {
+         Some(OneOrMany::One(alt)) if index == 0 => Some(alt),
+         Some(OneOrMany::Many(alts)) => alts.get(index),
+         _ => None,
+      }
+   }
+
+   /// Check that the image has alt text and focal point for every location
+   pub fn validate(&self) -> Result<(), String> {
+      let locations = self.location.iter().len();
+      if let Some(alt) = self.alt.as_ref().filter(|alt| alt.count() != locations) {
+         return Err(format!("image {} has {} alt texts for {} locations", self.location, alt.count(), locations));
+      }
+      if let Some(focus) = self.focus.as_ref().filter(|focus| focus.count() != locations) {
+         return Err(format!("image {} has {} focal points for {} locations", self.location, focus.count(), locations));
+      }
+      Ok(())
+   }
+
+   /// Get focal point for image at the index of location
+   pub fn get_focus(&self, index: usize) -> Option<&Focus> {
+      match &self.focus {
+         Some(FocusPoints::One(focus)) if index == 0 => Some(focus),
+         Some(FocusPoints::Many(focuses)) => focuses.get(index),
+         _ => None,
+      }
+   }
+
+   /// Check if the date is inside of the validity window of the image
+   pub fn is_available(&self, date: NaiveDate) -> bool {
+      match (&self.not_before, &self.not_after) {
+         // Recurring window can continue over the end of year
+         (Some(ImageDate::Recurring(start)), Some(ImageDate::Recurring(end))) if start > end => {
+            ImageDate::Recurring(*start).is_on_or_before(date) || ImageDate::Recurring(*end).is_on_or_after(date)
+         }
+         (not_before, not_after) => {
+            not_before.as_ref().is_none_or(|not_before| not_before.is_on_or_before(date))
+               && not_after.as_ref().is_none_or(|not_after| not_after.is_on_or_after(date))
+         }
+      }
+   }
+
+   pub fn get_weight(&self) -> f64 {
+      self.weight.unwrap_or(1.0)
+   }
+
+   pub fn get_album(&self) -> String {
+      match &self.album {
+         Some(album) => album.to_owned(),
+         None => {
+            let path = self.location.first().split(['?', '#']).next().unwrap_or_default();
+            path.rsplit_once('/').map_or("", |(parent, _)| parent).to_owned()
+         }
+      }
+   }
+}
+
+/// Date of the image validity window
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(into = "String", try_from = "String")]
+pub enum ImageDate {
+   /// Specific date
+   Fixed(NaiveDate),
+   /// Month and day repeating every year
+   Recurring((u32, u32)),
+}
+
+impl ImageDate {
+   /// Check if this date is on the date or before it
+   pub fn is_on_or_before(&self, date: NaiveDate) -> bool {
+      match self {
+         ImageDate::Fixed(fixed) => *fixed <= date,
+         ImageDate::Recurring(month_day) => *month_day <= (date.month(), date.day()),
+      }
+   }
+
+   /// Check if this date is on the date or after it
+   pub fn is_on_or_after(&self, date: NaiveDate) -> bool {
+      match self {
+         ImageDate::Fixed(fixed) => *fixed >= date,
+         ImageDate::Recurring(month_day) => *month_day >= (date.month(), date.day()),
+      }
+   }
+
+   /// Check if this date is the date
+   pub fn matches(&self, date: NaiveDate) -> bool {
+      self.is_on_or_before(date) && self.is_on_or_after(date)
+   }
+
+   /// Get the first day on the date or after it this date falls on
+   /// * `date` - Date to search from
+   pub fn next_on_or_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+      match self {
+         ImageDate::Fixed(fixed) => Some(*fixed).filter(|fixed| *fixed >= date),
+         // February 29 is found within 8 years
+         ImageDate::Recurring((month, day)) => (date.year()..=date.year() + 8)
+            .filter_map(|year| NaiveDate::from_ymd_opt(year, *month, *day))
+            .find(|next_date| *next_date >= date),
+      }
+   }
+
+   /// Check if both dates can fall on the same day
+   pub fn collides_with(&self, other: &ImageDate) -> bool {
+      match (self, other) {
+         (ImageDate::Fixed(date), other) | (other, ImageDate::Fixed(date)) => other.matches(*date),
+         (ImageDate::Recurring(month_day), ImageDate::Recurring(other_month_day)) => month_day == other_month_day,
+      }
+   }
+}
+
+impl TryFrom<String> for ImageDate {
+   type Error = String;
+
+   fn try_from(date: String) -> Result<Self, Self::Error> {
+      if let Ok(fixed) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+         return Ok(ImageDate::Fixed(fixed));
+      }
+      // Leap year is used so 02-29 is accepted
+      match NaiveDate::parse_from_str(&format!("2000-{}", date), "%Y-%m-%d") {
+         Ok(recurring) if date.len() == 5 => Ok(ImageDate::Recurring((recurring.month(), recurring.day()))),
+         _ => Err(format!("invalid date '{}', expected format YYYY-MM-DD or MM-DD", date)),
+      }
+   }
+}
+
+impl From<ImageDate> for String {
+   fn from(date: ImageDate) -> Self {
+      date.to_string()
+   }
+}
+
+impl Display for ImageDate {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      match self {
+         ImageDate::Fixed(fixed) => write!(f, "{}", fixed.format("%Y-%m-%d")),
+         ImageDate::Recurring((month, day)) => write!(f, "{:02}-{:02}", month, day),
+      }
+   }
+}
+
+/// Focal point of the image used for cropping of previews
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(into = "String", try_from = "String")]
+pub struct Focus {
+   /// Horizontal position from -1.0 (left) to 1.0 (right)
+   pub x: f64,
+   /// Vertical position from -1.0 (bottom) to 1.0 (top)
+   pub y: f64,
+}
+
+impl TryFrom<String> for Focus {
+   type Error = String;
+
+   fn try_from(focus: String) -> Result<Self, Self::Error> {
+      let parse = |value: Option<&str>| {
+         value.and_then(|value| value.trim().parse::<f64>().ok()).filter(|value| (-1.0..=1.0).contains(value))
+      };
+      let mut values = focus.split(',');
+      match (parse(values.next()), parse(values.next()), values.next()) {
+         (Some(x), Some(y), None) => Ok(Focus { x, y }),
+         _ => Err(format!("invalid focus '{}', expected format x,y with values from -1.0 to 1.0", focus)),
+      }
+   }
+}
+
+impl From<Focus> for String {
+   fn from(focus: Focus) -> Self {
+      focus.to_string()
+   }
+}
+
+impl Display for Focus {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{},{}", self.x, self.y)
+   }
+}
+
+/// Single focal point or list of focal points
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(into = "OneOrMany", try_from = "OneOrMany")]
+pub enum FocusPoints {
+   One(Focus),
+   Many(Vec<Focus>),
+}
+
+impl FocusPoints {
+   /// Number of focal points
+   pub fn count(&self) -> usize {
+      match self {
+         FocusPoints::One(_) => 1,
+         FocusPoints::Many(focuses) => focuses.len(),
+      }
+   }
+}
+
+impl TryFrom<OneOrMany> for FocusPoints {
+   type Error = String;
+
+   fn try_from(focus: OneOrMany) -> Result<Self, Self::Error> {
+      match focus {
+         OneOrMany::One(focus) => Ok(FocusPoints::One(Focus::try_from(focus)?)),
+         OneOrMany::Many(focuses) => Ok(FocusPoints::Many(focuses.into_iter().map(Focus::try_from).collect::<Result<_, _>>()?)),
+      }
+   }
+}
+
+impl From<FocusPoints> for OneOrMany {
+   fn from(focus: FocusPoints) -> Self {
+      match focus {
+         FocusPoints::One(focus) => OneOrMany::One(focus.to_string()),
+         FocusPoints::Many(focuses) => OneOrMany::Many(focuses.iter().map(Focus::to_string).collect()),
+      }
+   }
+}
+
+/// Single value or list of values
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum OneOrMany {
+   One(String),
+   Many(Vec<String>),
+}
+
+impl OneOrMany {
+   /// Number of values
+   pub fn count(&self) -> usize {
+      match self {
+         OneOrMany::One(_) => 1,
+         OneOrMany::Many(values) => values.len(),
+      }
+   }
+}
+
+impl Display for OneOrMany {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      match self {
+         OneOrMany::One(value) => write!(f, "{}", value),
+         OneOrMany::Many(values) => write!(f, "{}", values.join(" | ")),
+      }
+   }
+}
+
+/// Locations of images posted together in one post
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(into = "OneOrMany")]
+pub struct Locations(Vec<String>);
+
+impl Locations {
+   /// Maximal number of images in one post
+   pub const MAX: usize = 4;
+
+   pub fn first(&self) -> &String {
+      &self.0[0]
+   }
+
+   pub fn iter(&self) -> std::slice::Iter<'_, String> {
+      self.0.iter()
+   }
+
+   pub fn contains(&self, location: &str) -> bool {
+      self.0.iter().any(|item| item == location)
+   }
+}
+
+impl From<Locations> for OneOrMany {
+   fn from(locations: Locations) -> Self {
+      match <[String; 1]>::try_from(locations.0) {
+         Ok([location]) => OneOrMany::One(location),
+         Err(locations) => OneOrMany::Many(locations),
+      }
+   }
+}
+
+impl<'de> Deserialize<'de> for Locations {
+   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+   where
+      D: Deserializer<'de>,
+   {
+      match OneOrMany::deserialize(deserializer)? {
+         OneOrMany::One(location) => Ok(Locations(vec![location])),
+         OneOrMany::Many(locations) if (1..=Locations::MAX).contains(&locations.len()) => Ok(Locations(locations)),
+         OneOrMany::Many(locations) => {
+            Err(D::Error::custom(format!("location has to contain from 1 to {} images, got {}", Locations::MAX, locations.len())))
+         }
+      }
+   }
+}
+
+impl Display for Locations {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{}", self.0.join(", "))
+   }
+}
+
+/// Language codes from ISO 639-1
+const ISO_639_1: [&str; 184] = [
+   "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bh", "bi", "bm", "bn", "bo", "br",
+   "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da", "de", "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu",
+   "fa", "ff", "fi", "fj", "fo", "fr", "fy", "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy",
+   "hz", "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj", "kk", "kl", "km", "kn",
+   "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln", "lo", "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml",
+   "mn", "mr", "ms", "mt", "my", "na", "nb", "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os",
+   "pa", "pi", "pl", "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk", "sl", "sm", "sn",
+   "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti", "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw",
+   "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo", "wa", "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+fn from_language<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   match Option::<String>::deserialize(deserializer)? {
+      None => Ok(None),
+      Some(language) => match ISO_639_1.contains(&language.to_lowercase().as_str()) {
+         true => Ok(Some(language.to_lowercase())),
+         false => Err(D::Error::custom(format!("language '{}' is not two letter ISO 639-1 code", language))),
+      },
+   }
+}
+
+fn from_weight<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+   D: Deserializer<'de>,
+{
+   match Option::<f64>::deserialize(deserializer)? {
+      Some(weight) if !weight.is_finite() || weight < 0.0 => {
+         Err(D::Error::custom(format!("weight {} has to be a non-negative number", weight)))
+      }
+      weight => Ok(weight),
    }
 }
 
 ///Structure containing info about current used and unused images
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ImageDB {
    // List of used images
    pub used: Vec<String>,
@@ -275,6 +1224,147 @@ pub struct ImageDB {
    pub random_deck: Vec<String>,
    #[serde(default)]
    pub visiblity_sequence: usize,
+   // Next planned post, kept so the random jitter is not rolled again after restart
+   #[serde(default)]
+   pub next_post: Option<ScheduledPost>,
+   // Time from which the interval schedule is counted
+   #[serde(default)]
+   pub interval_anchor: Option<DateTime<FixedOffset>>,
+   // Last successfully posted slot and the time it was posted at
+   #[serde(default)]
+   pub last_post: Option<ScheduledPost>,
+   // State of retrying the failed post
+   #[serde(default)]
+   pub retry: Option<RetryState>,
+   // Last slot for which the posting was given up, it isn't posted as missed slot
+   #[serde(default)]
+   pub abandoned_slot: Option<DateTime<FixedOffset>>,
+   // Image that failed to be posted and how many times in row
+   #[serde(default)]
+   pub failed_image: Option<FailedImage>,
+   // Last image posted in order of selection, sequential selection continues after it
+   #[serde(default)]
+   pub sequence_last: Option<String>,
+   // Time of the last successful post of every image
+   #[serde(default)]
+   pub last_posted: HashMap<String, DateTime<FixedOffset>>,
+   // Position in the rotation of categories
+   #[serde(default)]
+   pub category_sequence: usize,
+   // State of seeded random number generator for picking images
+   #[serde(default)]
+   pub rng_state: Option<RngState>,
+   // Images forced to be posted next, in order of posting
+   #[serde(default)]
+   pub queue: Vec<String>,
+   // Images added to the queue since the last save, queue can be changed on disk from command line meanwhile
+   #[serde(skip)]
+   pub queue_added: Vec<String>,
+   // Images removed from the queue since the last save
+   #[serde(skip)]
+   pub queue_removed: Vec<String>,
+   // Series that failed to be posted completely
+   #[serde(default)]
+   pub series_progress: Option<SeriesProgress>,
+   // Idempotency key of status that wasn't confirmed as created yet, reused on retry so the server doesn't post duplicate
+   #[serde(default)]
+   pub idempotency_key: Option<IdempotencyKey>,
+   // Statuses scheduled on the server that weren't confirmed as published yet
+   #[serde(default)]
+   pub scheduled_statuses: Vec<ScheduledStatus>,
+}
+
+///Structure containing status scheduled on the server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledStatus {
+   /// Id of the scheduled status
+   pub id: String,
+   /// Hash of the image
+   pub hash: String,
+   /// Post time from the configuration the status is scheduled for
+   pub slot: DateTime<FixedOffset>,
+   /// Time the status is published at
+   pub time: DateTime<FixedOffset>,
+}
+
+///Structure containing idempotency key used for creating status with the image
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotencyKey {
+   /// Hash of the image
+   pub hash: String,
+   /// Post time the image was posted for
+   #[serde(default)]
+   pub slot: Option<DateTime<FixedOffset>>,
+   /// Value of Idempotency-Key header
+   pub key: String,
+}
+
+///Structure containing progress of partially posted series
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SeriesProgress {
+   /// Name of the series
+   pub series: String,
+   /// Number of already posted images of the series
+   pub posted: usize,
+   /// Id of the last posted status of the series, none for scheduled statuses as they can't be replied to
+   #[serde(default)]
+   pub status_id: Option<String>,
+}
+
+///Structure containing state of seeded random number generator
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RngState {
+   /// Seed from the configuration the state was derived from
+   pub seed: u64,
+   /// Seed for the next pick
+   pub state: u64,
+}
+
+///Structure containing state of retrying failed post
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetryState {
+   /// Slot for which the posting failed
+   pub slot: DateTime<FixedOffset>,
+   /// Number of failed attempts for the slot
+   pub attempts: u32,
+   /// Time of the next attempt
+   pub next_attempt: DateTime<FixedOffset>,
+}
+
+///Structure containing image that failed to be posted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailedImage {
+   /// Hash of the image
+   pub hash: String,
+   /// Number of failed attempts in row with the image
+   pub failures: u32,
+}
+
+///Structure containing planned post time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledPost {
+   /// Post time from the configuration
+   pub slot: DateTime<FixedOffset>,
+   /// Actual time of the post after applying jitter
+   pub time: DateTime<FixedOffset>,
+}
+
+impl Display for ScheduledPost {
+   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      let offset = self.time - self.slot;
+      if offset == Duration::zero() {
+         return write!(f, "{}", self.time);
+      }
+
+      let slot_format = if self.slot.date_naive() == self.time.date_naive() { "%H:%M" } else { "%Y-%m-%d %H:%M" };
+      let sign = if offset < Duration::zero() { '-' } else { '+' };
+      let offset = offset.num_seconds().abs();
+      let offset = match offset / 3600 {
+         0 => format!("{}m {}s", offset / 60, offset % 60),
+         hours => format!("{}h {}m {}s", hours, offset % 3600 / 60, offset % 60),
+      };
+      write!(f, "{} (slot {} with offset {}{})", self.time, self.slot.format(slot_format), sign, offset)
+   }
 }
 
 impl ImageDB {
@@ -283,13 +1373,90 @@ impl ImageDB {
    pub fn contains(&self, hash: &String) -> bool {
       self.used.contains(hash) || self.unused.contains(hash)
    }
+
+   /// Move image out of unused list to used list or remove it from random deck and queue
+   /// * `hash` - Hash of the image
+   pub fn mark_used(&mut self, hash: &String) {
+      self.dequeue(hash);
+      if let Some(pos) = self.unused.iter().position(|unused_hash| unused_hash == hash) {
+         self.unused.remove(pos);
+         self.used.push(hash.to_owned());
+      } else if let Some(pos) = self.random_deck.iter().position(|deck_hash| deck_hash == hash) {
+         self.random_deck.remove(pos);
+      }
+   }
+
+   /// Return image from used list back to unused list so it is posted again, images pinned to a date are not in the lists
+   /// * `hash` - Hash of the image
+   pub fn mark_unused(&mut self, hash: &String) {
+      if let Some(pos) = self.used.iter().position(|used_hash| used_hash == hash) {
+         self.used.remove(pos);
+         if !self.unused.contains(hash) {
+            self.unused.push(hash.to_owned());
+         }
+      }
+      self.last_posted.remove(hash);
+   }
+
+   /// Add image to the end of the queue, returns false if it is already queued
+   /// * `hash` - Hash of the image
+   pub fn enqueue(&mut self, hash: &String) -> bool {
+      if self.queue.contains(hash) {
+         return false;
+      }
+      self.queue.push(hash.to_owned());
+      self.queue_removed.retain(|removed_hash| removed_hash != hash);
+      self.queue_added.push(hash.to_owned());
+      true
+   }
+
+   /// Remove image from the queue, returns false if it isn't queued
+   /// * `hash` - Hash of the image
+   pub fn dequeue(&mut self, hash: &String) -> bool {
+      match self.queue.iter().position(|queued_hash| queued_hash == hash) {
+         Some(pos) => {
+            self.queue.remove(pos);
+            self.queue_added.retain(|added_hash| added_hash != hash);
+            self.queue_removed.push(hash.to_owned());
+            true
+         }
+         None => false,
+      }
+   }
+
+   /// Replace the queue with the queue stored on disk and apply changes made since the last save to it
+   /// * `stored_queue` - Queue read from the database file
+   pub fn merge_queue(&mut self, mut stored_queue: Vec<String>) {
+      stored_queue.retain(|hash| !self.queue_removed.contains(hash));
+      for hash in &self.queue_added {
+         if !stored_queue.contains(hash) {
+            stored_queue.push(hash.to_owned());
+         }
+      }
+      self.queue = stored_queue;
+   }
+}
+
+///Queue of the database stored on disk
+#[derive(Deserialize)]
+struct StoredQueue {
+   #[serde(default)]
+   queue: Vec<String>,
 }
 
 ///Save used and unused images to file.
+///
+///Queue is merged with the queue stored on disk, so images queued from command line meanwhile are not lost
 pub fn save_images_ids(internal_db: &mut ImageDB, app_config: &Config) {
-   match File::create(app_config.get_internal_database_path()) {
+   let path = app_config.get_internal_database_path();
+   if let Some(stored) = std::fs::read(&path).ok().and_then(|data| serde_json::from_slice::<StoredQueue>(&data).ok()) {
+      internal_db.merge_queue(stored.queue);
+   }
+   match File::create(path) {
       Ok(mut file) => {
          file.write_all(serde_json::to_string(&internal_db).unwrap().as_bytes()).unwrap();
+         internal_db.queue_added.clear();
+         internal_db.queue_removed.clear();
       }
       Err(e) => {
          app_config.output_message(
@@ -300,3 +1467,216 @@ pub fn save_images_ids(internal_db: &mut ImageDB, app_config: &Config) {
       }
    };
 }
+
+/// Configuration for tests with the required fields filled in, fields of `extra` are added to it
+/// * `extra` - Json object with additional fields of the configuration
+#[cfg(test)]
+pub fn test_config(extra: serde_json::Value) -> Config {
+   let mut config = serde_json::json!({
+      "server": "http://localhost",
+      "token": "token",
+      "image_json": "sources.json",
+      "internal_database": "db.json",
+      "timezone": "UTC",
+      "log_level": "Error",
+   });
+   config.as_object_mut().expect("config is object").extend(extra.as_object().expect("extra is object").clone());
+   serde_json::from_value(config).expect("valid config")
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn image(json: serde_json::Value) -> Image {
+      serde_json::from_value(json).expect("valid image")
+   }
+
+   fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+      NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+   }
+
+   fn time(hours: u32, minutes: u32) -> NaiveTime {
+      NaiveTime::from_hms_opt(hours, minutes, 0).expect("valid time")
+   }
+
+   #[test]
+   fn parse_duration_sums_units() {
+      assert_eq!(parse_duration("90s"), Ok(Duration::seconds(90)));
+      assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+      assert_eq!(parse_duration(" 2d 3h "), Ok(Duration::hours(51)));
+      assert_eq!(parse_duration("366d"), Ok(Duration::days(366)));
+   }
+
+   #[test]
+   fn parse_duration_rejects_invalid() {
+      for duration in ["", "10", "h", "5x", "1h30", "367d", "999999999999999d", "99999999999999999999s"] {
+         assert!(parse_duration(duration).is_err(), "duration '{}' should be rejected", duration);
+      }
+   }
+
+   #[test]
+   fn parse_jitter_accepts_prefixes() {
+      for jitter in ["±20m", "+-20m", "20m"] {
+         assert_eq!(parse_jitter(jitter), Ok(Duration::minutes(20)));
+      }
+      assert_eq!(parse_jitter("±12h"), Ok(Duration::hours(12)));
+      assert!(parse_jitter("±12h1s").is_err());
+      assert!(parse_jitter("±3650000d").is_err());
+   }
+
+   #[test]
+   fn quiet_hours_over_midnight() {
+      let quiet_hours = QuietHours { start: time(22, 0), end: time(7, 0) };
+      for inside in [time(22, 0), time(23, 59), time(0, 0), time(6, 59)] {
+         assert!(quiet_hours.contains(&inside), "{} should be quiet", inside);
+      }
+      for outside in [time(7, 0), time(12, 0), time(21, 59)] {
+         assert!(!quiet_hours.contains(&outside), "{} shouldn't be quiet", outside);
+      }
+   }
+
+   #[test]
+   fn quiet_hours_within_day() {
+      let quiet_hours = QuietHours { start: time(12, 0), end: time(14, 0) };
+      assert!(quiet_hours.contains(&time(12, 0)));
+      assert!(quiet_hours.contains(&time(13, 59)));
+      assert!(!quiet_hours.contains(&time(14, 0)));
+      assert!(!quiet_hours.contains(&time(0, 0)));
+   }
+
+   #[test]
+   fn image_date_parsing() {
+      assert_eq!(ImageDate::try_from("2024-02-29".to_string()), Ok(ImageDate::Fixed(date(2024, 2, 29))));
+      assert_eq!(ImageDate::try_from("02-29".to_string()), Ok(ImageDate::Recurring((2, 29))));
+      for invalid in ["2023-02-29", "13-01", "2-1", "12-32", "christmas"] {
+         assert!(ImageDate::try_from(invalid.to_string()).is_err(), "date '{}' should be rejected", invalid);
+      }
+   }
+
+   #[test]
+   fn recurring_window_wraps_over_year_end() {
+      let image = image(serde_json::json!({"location": "a.jpg", "not_before": "12-20", "not_after": "01-06"}));
+      for inside in [date(2026, 12, 20), date(2026, 12, 31), date(2027, 1, 1), date(2027, 1, 6)] {
+         assert!(image.is_available(inside), "{} should be available", inside);
+      }
+      for outside in [date(2026, 12, 19), date(2027, 1, 7), date(2027, 6, 1)] {
+         assert!(!image.is_available(outside), "{} shouldn't be available", outside);
+      }
+   }
+
+   #[test]
+   fn recurring_window_within_year() {
+      let image = image(serde_json::json!({"location": "a.jpg", "not_before": "03-01", "not_after": "05-31"}));
+      assert!(image.is_available(date(2026, 3, 1)));
+      assert!(image.is_available(date(2026, 5, 31)));
+      assert!(!image.is_available(date(2026, 2, 28)));
+      assert!(!image.is_available(date(2026, 6, 1)));
+   }
+
+   #[test]
+   fn fixed_window_bounds() {
+      let image = image(serde_json::json!({"location": "a.jpg", "not_before": "2026-03-01"}));
+      assert!(!image.is_available(date(2026, 2, 28)));
+      assert!(image.is_available(date(2026, 3, 1)));
+      assert!(image.is_available(date(2030, 1, 1)));
+   }
+
+   #[test]
+   fn image_dates_collide() {
+      let recurring = ImageDate::Recurring((10, 18));
+      assert!(recurring.collides_with(&ImageDate::Fixed(date(2026, 10, 18))));
+      assert!(recurring.collides_with(&ImageDate::Recurring((10, 18))));
+      assert!(!recurring.collides_with(&ImageDate::Fixed(date(2026, 10, 19))));
+   }
+
+   #[test]
+   fn standard_days_of_week_are_converted() {
+      assert_eq!(convert_days_of_week("1-5"), Ok("2,3,4,5,6".to_string()));
+      assert_eq!(convert_days_of_week("0"), Ok("1".to_string()));
+      assert_eq!(convert_days_of_week("7"), Ok("1".to_string()));
+      assert_eq!(convert_days_of_week("*/2"), Ok("1,3,5,7".to_string()));
+      assert_eq!(convert_days_of_week("MON-FRI"), Ok("MON-FRI".to_string()));
+      assert_eq!(convert_days_of_week("*"), Ok("*".to_string()));
+      assert_eq!(convert_days_of_week("MON/2"), Ok("MON/2".to_string()));
+      assert!(convert_days_of_week("8").is_err());
+      assert!(convert_days_of_week("MON-8").is_err());
+      assert!(convert_days_of_week("5-1").is_err());
+   }
+
+   #[test]
+   fn days_of_week_mixing_names_and_numbers_are_converted() {
+      assert_eq!(convert_days_of_week("MON-5"), Ok("2,3,4,5,6".to_string()));
+      assert_eq!(convert_days_of_week("5-Sat"), Ok("6,7".to_string()));
+      assert_eq!(convert_days_of_week("sunday-2"), Ok("1,2,3".to_string()));
+   }
+
+   #[test]
+   fn five_field_cron_runs_on_weekdays() {
+      let Ok(PostTimeKind::Cron(schedule)) = parse_cron_time("0 9 * * 1-5") else {
+         panic!("cron expression should be parsed");
+      };
+      let weekdays: Vec<Weekday> = schedule.upcoming(Utc).take(7).map(|date_time| date_time.weekday()).collect();
+      assert!(!weekdays.contains(&Weekday::Sat));
+      assert!(!weekdays.contains(&Weekday::Sun));
+      assert!(weekdays.contains(&Weekday::Fri));
+   }
+
+   #[test]
+   fn next_date_of_pinned_image() {
+      let today = date(2026, 10, 16);
+      assert_eq!(ImageDate::Recurring((10, 16)).next_on_or_after(today), Some(today));
+      assert_eq!(ImageDate::Recurring((3, 1)).next_on_or_after(today), Some(date(2027, 3, 1)));
+      assert_eq!(ImageDate::Recurring((2, 29)).next_on_or_after(today), Some(date(2028, 2, 29)));
+      assert_eq!(ImageDate::Fixed(date(2026, 10, 15)).next_on_or_after(today), None);
+   }
+
+   #[test]
+   fn alt_texts_have_to_match_locations() {
+      assert!(image(serde_json::json!({"location": "a.jpg", "alt": "a"})).validate().is_ok());
+      assert!(image(serde_json::json!({"location": ["a.jpg", "b.jpg"], "alt": ["a", "b"]})).validate().is_ok());
+      assert!(image(serde_json::json!({"location": ["a.jpg", "b.jpg"]})).validate().is_ok());
+      let error =
+         image(serde_json::json!({"location": ["a.jpg", "b.jpg"], "alt": "a"})).validate().expect_err("missing alt text");
+      assert!(error.contains("a.jpg, b.jpg"));
+      assert!(image(serde_json::json!({"location": "a.jpg", "alt": ["a", "b"]})).validate().is_err());
+   }
+
+   #[test]
+   fn focus_has_to_be_in_range() {
+      assert_eq!(Focus::try_from("0.5,-1".to_string()), Ok(Focus { x: 0.5, y: -1.0 }));
+      for invalid in ["1.5,0", "0", "0,0,0", "a,b", "NaN,0"] {
+         assert!(Focus::try_from(invalid.to_string()).is_err(), "focus '{}' should be rejected", invalid);
+      }
+   }
+
+   #[test]
+   fn focal_points_have_to_match_locations() {
+      assert!(image(serde_json::json!({"location": ["a.jpg", "b.jpg"], "focus": ["0,0", "0.5,0.5"]})).validate().is_ok());
+      let error =
+         image(serde_json::json!({"location": ["a.jpg", "b.jpg"], "focus": "0,0"})).validate().expect_err("missing focal point");
+      assert!(error.contains("a.jpg, b.jpg"));
+      assert!(image(serde_json::json!({"location": "a.jpg", "focus": ["0,0", "0,0"]})).validate().is_err());
+   }
+
+   #[test]
+   fn queue_changed_on_disk_between_read_and_save_is_kept() {
+      let path = std::env::temp_dir().join(format!("vulpes_porto_queue_test_{}.json", std::process::id()));
+      let config = test_config(serde_json::json!({ "internal_database": path.to_str().expect("utf-8 path") }));
+      let mut daemon_db =
+         ImageDB { unused: vec!["a".into(), "b".into(), "c".into()], queue: vec!["a".into(), "b".into()], ..Default::default() };
+      save_images_ids(&mut daemon_db, &config);
+
+      //Image is queued from command line while the bot holds the database in memory
+      let mut cli_db: ImageDB = serde_json::from_slice(&std::fs::read(&path).expect("database written")).expect("valid database");
+      cli_db.enqueue(&"c".to_string());
+      save_images_ids(&mut cli_db, &config);
+
+      daemon_db.mark_used(&"a".to_string());
+      save_images_ids(&mut daemon_db, &config);
+      let stored_db: ImageDB = serde_json::from_slice(&std::fs::read(&path).expect("database written")).expect("valid database");
+      std::fs::remove_file(&path).expect("database removed");
+      assert_eq!(stored_db.queue, vec!["b".to_string(), "c".to_string()]);
+      assert_eq!(daemon_db.queue, stored_db.queue);
+   }
+}